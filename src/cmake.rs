@@ -0,0 +1,391 @@
+use anyhow::{bail, Context, Result};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cps::{
+    load_cps, Component, ComponentFields, Configuration, LanguageStringList, MaybeComponent,
+    Package, PREFIX_TOKEN,
+};
+use crate::flags::install_prefix;
+
+/// Quote a value for a CMake argument, escaping backslashes and quotes but not `$`, which
+/// starts the generator expressions of configuration-specific values
+fn quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// The imported target of a component requirement, `:bar` and `foo:bar` name component `bar`
+/// of this package and of package `foo` and a bare `foo` the package `foo`
+fn requirement_target(package: &Package, required: &str) -> String {
+    match required.split_once(':') {
+        Some(("", component)) => format!("{}::{}", package.name, component),
+        Some((other, component)) => format!("{}::{}", other, component),
+        None => format!("{}::{}", required, required),
+    }
+}
+
+/// The CMake compile feature of a CPS one, e.g. `cxx_std_17` for `c++17`
+fn compile_feature(feature: &str) -> Option<String> {
+    let standard = |prefixes: &[&str]| {
+        prefixes
+            .iter()
+            .find_map(|prefix| feature.strip_prefix(prefix))
+            .filter(|version| version.chars().all(|c| c.is_ascii_digit()))
+    };
+    if let Some(version) = standard(&["c++", "gnu++"]) {
+        Some(format!("cxx_std_{}", version))
+    } else {
+        standard(&["c", "gnu"]).map(|version| format!("c_std_{}", version))
+    }
+}
+
+fn language_values(list: &Option<LanguageStringList>) -> Vec<String> {
+    list.iter()
+        .flat_map(LanguageStringList::values)
+        .cloned()
+        .collect()
+}
+
+/// The interface properties of a component or one of its configurations
+#[derive(Default)]
+struct Usage {
+    includes: Vec<String>,
+    definitions: Vec<String>,
+    compile_options: Vec<String>,
+    compile_features: Vec<String>,
+    link_libraries: Vec<String>,
+    link_options: Vec<String>,
+}
+
+impl Usage {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        package: &Package,
+        includes: &Option<LanguageStringList>,
+        definitions: &Option<LanguageStringList>,
+        compile_flags: &Option<LanguageStringList>,
+        compile_features: &Option<Vec<String>>,
+        requires: &Option<Vec<String>>,
+        link_libraries: &Option<Vec<String>>,
+        link_flags: &Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            includes: language_values(includes),
+            definitions: language_values(definitions),
+            compile_options: language_values(compile_flags),
+            compile_features: compile_features
+                .iter()
+                .flatten()
+                .filter_map(|feature| compile_feature(feature))
+                .collect(),
+            link_libraries: requires
+                .iter()
+                .flatten()
+                .map(|required| requirement_target(package, required))
+                .chain(link_libraries.iter().flatten().cloned())
+                .collect(),
+            link_options: link_flags.iter().flatten().cloned().collect(),
+        }
+    }
+
+    fn of_component(package: &Package, fields: &ComponentFields) -> Self {
+        Self::new(
+            package,
+            &fields.includes,
+            &fields.definitions,
+            &fields.compile_flags,
+            &fields.compile_features,
+            &fields.requires,
+            &fields.link_libraries,
+            &fields.link_flags,
+        )
+    }
+
+    fn of_configuration(package: &Package, configuration: &Configuration) -> Self {
+        Self::new(
+            package,
+            &configuration.includes,
+            &configuration.definitions,
+            &configuration.compile_flags,
+            &configuration.compile_features,
+            &configuration.requires,
+            &configuration.link_libraries,
+            &configuration.link_flags,
+        )
+    }
+
+    /// Add the properties of `configuration`, only applying when it is the selected one
+    fn extend_configuration(&mut self, name: &str, configuration: Usage) {
+        let only = |values: Vec<String>| {
+            values
+                .into_iter()
+                .map(move |value| format!("$<$<CONFIG:{}>:{}>", name, value))
+        };
+        self.includes.extend(only(configuration.includes));
+        self.definitions.extend(only(configuration.definitions));
+        self.compile_options
+            .extend(only(configuration.compile_options));
+        self.compile_features
+            .extend(only(configuration.compile_features));
+        self.link_libraries
+            .extend(only(configuration.link_libraries));
+        self.link_options.extend(only(configuration.link_options));
+    }
+
+    fn properties(self) -> Vec<(String, Vec<String>)> {
+        [
+            ("INTERFACE_INCLUDE_DIRECTORIES", self.includes),
+            ("INTERFACE_COMPILE_DEFINITIONS", self.definitions),
+            ("INTERFACE_COMPILE_OPTIONS", self.compile_options),
+            ("INTERFACE_COMPILE_FEATURES", self.compile_features),
+            ("INTERFACE_LINK_LIBRARIES", self.link_libraries),
+            ("INTERFACE_LINK_OPTIONS", self.link_options),
+        ]
+        .into_iter()
+        .map(|(property, values)| (property.to_string(), values))
+        .collect()
+    }
+}
+
+/// Write the `declaration` importing `target`, e.g. `add_library(foo::foo SHARED IMPORTED)`,
+/// followed by the properties of the target
+fn write_target(
+    cmake: &mut String,
+    target: &str,
+    declaration: &str,
+    properties: Vec<(String, Vec<String>)>,
+) -> std::fmt::Result {
+    let properties: Vec<_> = properties
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .collect();
+    writeln!(cmake, "if(NOT TARGET {})", target)?;
+    writeln!(cmake, "  {}", declaration)?;
+    if !properties.is_empty() {
+        writeln!(cmake, "  set_target_properties({} PROPERTIES", target)?;
+        for (property, values) in properties {
+            writeln!(cmake, "    {} {}", property, quote(&values.join(";")))?;
+        }
+        writeln!(cmake, "  )")?;
+    }
+    writeln!(cmake, "endif()")
+}
+
+/// Render the `<name>-config.cmake` of `package`, importing each of its components as the
+/// target `<name>::<component>`
+///
+/// Archives, dylibs and modules become imported `STATIC`, `SHARED` and `MODULE` libraries with
+/// their `IMPORTED_LOCATION`, interface and symbolic components `INTERFACE` libraries and exe
+/// components imported executables, which must have a location. Jars have no CMake equivalent
+/// and are left out. The packages `package` requires are found with `find_dependency` first. The
+/// attributes of a configuration only apply when CMake builds the configuration of the same
+/// name. `@prefix@` is replaced by `prefix`, which must be known if the package uses it.
+pub fn cmake_config(package: &Package, prefix: Option<&Path>) -> Result<String> {
+    let mut package = package.clone();
+    if let Some(prefix) = prefix {
+        package.resolve_paths(prefix);
+    }
+
+    let mut cmake = format!(
+        "# Generated by cps-deps from the CPS of package `{}`\n",
+        package.name
+    );
+    // The targets of the required packages must exist before the targets linking to them
+    let mut required: Vec<_> = package.requires.iter().flat_map(|map| map.keys()).collect();
+    required.sort();
+    if !required.is_empty() {
+        writeln!(cmake, "\ninclude(CMakeFindDependencyMacro)")?;
+        for name in required {
+            writeln!(cmake, "find_dependency({})", name)?;
+        }
+    }
+    let mut names: Vec<_> = package.components.keys().collect();
+    names.sort();
+    for name in names {
+        let target = format!("{}::{}", package.name, name);
+        let component = match &package.components[name] {
+            MaybeComponent::Component(component) => component,
+            MaybeComponent::Other(value) => {
+                if value["type"] == "exe" {
+                    let Some(location) = value["location"].as_str() else {
+                        bail!(
+                            "Unable to import exe component `{}` of package `{}`: it has no \
+                             location",
+                            name,
+                            package.name
+                        );
+                    };
+                    let properties =
+                        vec![("IMPORTED_LOCATION".to_string(), vec![location.to_string()])];
+                    writeln!(cmake)?;
+                    let declaration = format!("add_executable({} IMPORTED)", target);
+                    write_target(&mut cmake, &target, &declaration, properties)?;
+                }
+                continue;
+            }
+        };
+        let library_type = match component {
+            Component::Archive(_) => "STATIC",
+            Component::Dylib(_) => "SHARED",
+            Component::Module(_) => "MODULE",
+            Component::Interface(_) | Component::Symbolic(_) => "INTERFACE",
+            Component::Jar(_) | Component::Unknown => continue,
+        };
+        let Some(fields) = component.fields() else {
+            continue;
+        };
+
+        let mut properties = Vec::new();
+        let mut usage = Usage::of_component(&package, fields);
+        let mut configurations: Vec<_> = fields.configurations.iter().flatten().collect();
+        configurations.sort_by_key(|(name, _)| *name);
+        if library_type != "INTERFACE" {
+            if let Some(location) = &fields.location {
+                properties.push(("IMPORTED_LOCATION".to_string(), vec![location.clone()]));
+            }
+            let located: Vec<_> = configurations
+                .iter()
+                .filter_map(|(name, configuration)| Some((name, configuration.location.as_ref()?)))
+                .collect();
+            if !located.is_empty() {
+                properties.push((
+                    "IMPORTED_CONFIGURATIONS".to_string(),
+                    located
+                        .iter()
+                        .map(|(name, _)| name.to_uppercase())
+                        .collect(),
+                ));
+            }
+            for (name, location) in located {
+                properties.push((
+                    format!("IMPORTED_LOCATION_{}", name.to_uppercase()),
+                    vec![location.clone()],
+                ));
+            }
+        }
+        for (name, configuration) in configurations {
+            usage.extend_configuration(name, Usage::of_configuration(&package, configuration));
+        }
+        properties.extend(usage.properties());
+
+        writeln!(cmake)?;
+        let declaration = format!("add_library({} {} IMPORTED)", target, library_type);
+        write_target(&mut cmake, &target, &declaration, properties)?;
+    }
+
+    if cmake.contains(PREFIX_TOKEN) {
+        bail!(
+            "Unable to resolve `{}` in package `{}`: its install prefix is unknown, pass --prefix",
+            PREFIX_TOKEN,
+            package.name
+        );
+    }
+    Ok(cmake)
+}
+
+/// Write the `<name>-config.cmake` of the cps file at `cps` into `outdir`, see
+/// [`cmake_config`]
+///
+/// The install prefix is `prefix` if given and otherwise found from the location of the cps
+/// file, see [`install_prefix`]. Returns the path of the written file.
+pub fn export_cmake(cps: &Path, outdir: &Path, prefix: Option<&Path>) -> Result<PathBuf> {
+    let package = load_cps(cps)?;
+    let prefix = prefix
+        .map(PathBuf::from)
+        .or_else(|| install_prefix(&package, cps));
+    let cmake = cmake_config(&package, prefix.as_deref())?;
+
+    fs::create_dir_all(outdir)?;
+    let path = outdir.join(format!("{}-config.cmake", package.name));
+    fs::write(&path, cmake).with_context(|| format!("failed to write `{}`", path.display()))?;
+    Ok(path)
+}
+
+#[test]
+fn test_cmake_config_sample() -> Result<()> {
+    let package: Package = include_str!("../testdata/sample.cps").parse()?;
+    let cmake = cmake_config(&package, Some(Path::new("/opt/sample")))?;
+
+    assert!(cmake.contains("add_library(sample::sample-core INTERFACE IMPORTED)"));
+    assert!(cmake.contains("add_library(sample::sample-shared SHARED IMPORTED)"));
+    assert!(cmake.contains("add_library(sample::sample-static STATIC IMPORTED)"));
+    assert!(cmake.contains("add_executable(sample::sample-tool IMPORTED)"));
+    assert!(!cmake.contains("sample::sample-java"));
+    assert!(cmake
+        .contains("    IMPORTED_LOCATION_OPTIMIZED \"/opt/sample/lib64/libsample.so.1.2.0\"\n"));
+    assert!(cmake.contains("    IMPORTED_LOCATION_DEBUG \"/opt/sample/lib64/libsample_d.a\"\n"));
+    assert!(cmake.contains("    IMPORTED_CONFIGURATIONS \"DEBUG;OPTIMIZED\"\n"));
+    assert!(cmake.contains("    INTERFACE_INCLUDE_DIRECTORIES \"/opt/sample/include\"\n"));
+    assert!(cmake.contains("    IMPORTED_LOCATION \"/opt/sample/bin/sample-tool\"\n"));
+    assert!(cmake.contains(
+        "    INTERFACE_LINK_LIBRARIES \"$<$<CONFIG:shared>:sample::sample-shared>;\
+         $<$<CONFIG:static>:sample::sample-static>\"\n"
+    ));
+
+    assert!(cmake_config(&package, None).is_err());
+
+    // an exe without a location can't be imported
+    let mut package = package;
+    if let Some(MaybeComponent::Other(value)) = package.components.get_mut("sample-tool") {
+        value
+            .as_object_mut()
+            .expect("a component is an object")
+            .remove("location");
+    }
+    let error = cmake_config(&package, Some(Path::new("/opt/sample"))).unwrap_err();
+    assert!(error.to_string().contains("sample-tool"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn test_export_cmake() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let cps = dir.write(
+        "prefix/lib/cps/foo.cps",
+        r#"{
+            "name": "foo",
+            "cps_version": "0.11.0",
+            "cps_path": "@prefix@/lib/cps",
+            "components": {
+                "foo": {
+                    "type": "dylib",
+                    "location": "@prefix@/lib/libfoo.so",
+                    "includes": ["@prefix@/include"],
+                    "definitions": ["FOO=\"1\""],
+                    "compile_features": ["c++17", "threads"],
+                    "requires": ["bar"]
+                }
+            },
+            "requires": { "bar": {} }
+        }"#,
+    );
+
+    let path = export_cmake(&cps, &dir.path().join("cmake"), None)?;
+    assert_eq!(path, dir.path().join("cmake/foo-config.cmake"));
+    let prefix = dir.path().join("prefix");
+    assert_eq!(
+        fs::read_to_string(path)?,
+        format!(
+            "# Generated by cps-deps from the CPS of package `foo`
+
+include(CMakeFindDependencyMacro)
+find_dependency(bar)
+
+if(NOT TARGET foo::foo)
+  add_library(foo::foo SHARED IMPORTED)
+  set_target_properties(foo::foo PROPERTIES
+    IMPORTED_LOCATION \"{prefix}/lib/libfoo.so\"
+    INTERFACE_INCLUDE_DIRECTORIES \"{prefix}/include\"
+    INTERFACE_COMPILE_DEFINITIONS \"FOO=\\\"1\\\"\"
+    INTERFACE_COMPILE_FEATURES \"cxx_std_17\"
+    INTERFACE_LINK_LIBRARIES \"bar::bar\"
+  )
+endif()
+",
+            prefix = prefix.display()
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::cps::Package;
+use crate::discovery::{self, DiscoveryOptions};
+use crate::generate_from_pkg_config::{self, GenerateOptions, Indent, Overwrite};
+use crate::lib_search::MatchOrder;
+
+/// Everything controlling how pc files are discovered, parsed and converted
+///
+/// Built with [`Config::builder`], the defaults match running pkg-config on the host.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub discovery: DiscoveryOptions,
+    pub generate: GenerateOptions,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Convert each discovered pc file to a CPS package
+    pub fn generate(&self) -> Result<Vec<(PathBuf, Result<Package>)>> {
+        let pc_files = discovery::discover(&self.discovery)?;
+        Ok(generate_from_pkg_config::convert_all(
+            &pc_files,
+            &self.generate,
+        ))
+    }
+}
+
+/// Builds a [`Config`] one setting at a time
+#[derive(Debug, Default, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Search `dir` for pc files, replacing the default search directories
+    pub fn search_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.discovery.search_dirs.push(dir.into());
+        self
+    }
+
+    /// Also discover pc files that are symlinks
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.config.discovery.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Only keep the pc files whose file stem or package name matches the glob `pattern`
+    pub fn only(mut self, pattern: &str) -> Self {
+        self.config.discovery.only.push(pattern.to_string());
+        self
+    }
+
+    /// Leave out the pc files whose file stem or package name matches the glob `pattern`
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.config.discovery.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Use the pc files listed in the file at `path` instead of searching for them
+    pub fn from_list(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.discovery.from_list = Some(path.into());
+        self
+    }
+
+    /// Fail on references to undefined pkg-config variables
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.generate.parse.strict = strict;
+        self
+    }
+
+    /// Override the pkg-config variable `name` defined in the pc files with `value`
+    pub fn define_variable(mut self, name: &str, value: &str) -> Self {
+        self.config
+            .generate
+            .parse
+            .define_variables
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Define `prefix` as the install prefix of each pc file, like pkg-config's
+    /// `--define-prefix`
+    pub fn define_prefix(mut self, define_prefix: bool) -> Self {
+        self.config.generate.parse.define_prefix = define_prefix;
+        self
+    }
+
+    /// Print the pkg-config variable expansion steps to stderr
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.config.generate.trace = trace;
+        self
+    }
+
+    /// Treat `Requires.internal` dependencies as requirements of the package
+    pub fn include_requires_internal(mut self, include_requires_internal: bool) -> Self {
+        self.config.generate.include_requires_internal = include_requires_internal;
+        self
+    }
+
+    /// Make the shared libraries found in a `modules` or `plugins` directory `module`
+    /// components
+    pub fn plugin_modules(mut self, plugin_modules: bool) -> Self {
+        self.config.generate.plugin_modules = plugin_modules;
+        self
+    }
+
+    /// Fill in the platform of the generated packages from the host
+    pub fn platform(mut self, platform: bool) -> Self {
+        self.config.generate.platform = platform;
+        self
+    }
+
+    /// Replace the common spellings of licenses with their SPDX identifier
+    pub fn spdx_license(mut self, spdx_license: bool) -> Self {
+        self.config.generate.spdx_license = spdx_license;
+        self
+    }
+
+    /// Resolve symlinks in the include and library paths that exist
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.config.generate.canonicalize = canonicalize;
+        self
+    }
+
+    /// Name the default component after the package rather than its first library
+    pub fn name_default_component_after_package(mut self, after_package: bool) -> Self {
+        self.config.generate.name_default_component_after_package = after_package;
+        self
+    }
+
+    /// Print how a single pc file is converted to stderr
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.config.generate.explain = explain;
+        self
+    }
+
+    /// Write minified JSON instead of pretty-printing it
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.config.generate.compact = compact;
+        self
+    }
+
+    /// What to do when a cps file to be written already exists
+    pub fn overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.config.generate.overwrite = overwrite;
+        self
+    }
+
+    /// Draw a progress bar on stderr when it is a terminal
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.config.generate.progress = progress;
+        self
+    }
+
+    /// Don't draw the progress bar or report skipped files
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.config.generate.quiet = quiet;
+        self
+    }
+
+    /// Indent the pretty-printed JSON by this many spaces or a tab
+    pub fn indent(mut self, indent: Indent) -> Self {
+        self.config.generate.indent = indent;
+        self
+    }
+
+    /// Write an index of the generated packages into the output directory
+    pub fn index(mut self, index: bool) -> Self {
+        self.config.generate.index = index;
+        self
+    }
+
+    /// Translate the compile flag `flag` to the compile feature `feature`
+    pub fn feature(mut self, flag: &str, feature: &str) -> Self {
+        self.config.generate.features.register(flag, feature);
+        self
+    }
+
+    /// Find libraries under `sysroot`
+    pub fn sysroot(mut self, sysroot: impl Into<PathBuf>) -> Self {
+        self.config.generate.library_search.sysroot = Some(sysroot.into());
+        self
+    }
+
+    /// Find shared and static libraries by these extensions, e.g. `dylib` and `a` on macOS
+    pub fn library_extensions(mut self, dylib: &str, archive: &str) -> Self {
+        let search = &mut self.config.generate.library_search;
+        search.dylib_extension = dylib.to_string();
+        search.archive_extension = archive.to_string();
+        self
+    }
+
+    /// Find link stubs by this extension when a shared library is missing, e.g. `tbd` on macOS
+    pub fn dylib_stub_extension(mut self, extension: Option<&str>) -> Self {
+        self.config.generate.library_search.dylib_stub_extension = extension.map(String::from);
+        self
+    }
+
+    /// Resolve shared libraries through the dynamic linker cache
+    pub fn use_ldconfig(mut self, use_ldconfig: bool) -> Self {
+        self.config.generate.library_search.use_ldconfig = use_ldconfig;
+        self
+    }
+
+    /// Which library to choose when it is in more than one search path
+    pub fn match_order(mut self, match_order: MatchOrder) -> Self {
+        self.config.generate.library_search.match_order = match_order;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+#[test]
+fn test_generate_with_config() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let archive = dir.write("sysroot/opt/foo/lib/libcps-deps-config.lib", "");
+    dir.write(
+        "pc/foo.pc",
+        "prefix=/opt/foo\nName: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+         Cflags: -I${prefix}/include -std=c++17 -fexceptions\n\
+         Libs: -L${prefix}/lib -lcps-deps-config\n",
+    );
+    dir.write("pc/broken.pc", "Name: broken\nVersion: ${undefined}\n");
+
+    let config = Config::builder()
+        .search_dir(dir.path().join("pc"))
+        .strict(true)
+        .sysroot(dir.path().join("sysroot"))
+        .library_extensions("so", "lib")
+        .feature("-fexceptions", "exceptions")
+        .build();
+    let results = config.generate()?;
+    assert_eq!(results.len(), 2);
+
+    let (path, broken) = &results[0];
+    assert!(path.ends_with("broken.pc"));
+    assert!(broken.is_err());
+
+    let (path, package) = &results[1];
+    assert!(path.ends_with("foo.pc"));
+    let json = serde_json::to_value(package.as_ref().unwrap())?;
+    assert_eq!(
+        json["components"]["cps-deps-config"],
+        serde_json::json!({
+            "type": "archive",
+            "location": archive.to_str().unwrap(),
+            "compile_features": ["c++17", "exceptions"],
+            "includes": { "*": ["/opt/foo/include"] },
+            "link_languages": ["c++"]
+        })
+    );
+    Ok(())
+}
@@ -1,12 +1,30 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use std::{collections::HashMap, fs::File, io::BufReader, path::Path, str::FromStr};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::flags::ResolvedFlags;
+use crate::version::{self, VersionSchema};
+use crate::Error;
 
 const CPS_VERSION: &str = "0.11.0";
 
+/// Placeholder for the install prefix of a package in its paths, e.g. `@prefix@/include`
+pub const PREFIX_TOKEN: &str = "@prefix@";
+
+/// Component types of the CPS schema that are kept as raw JSON rather than modeled
+const OTHER_COMPONENT_TYPES: &[&str] = &["exe"];
+
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Platform {
     pub c_runtime_vendor: Option<String>,
     pub c_runtime_version: Option<String>,
@@ -22,15 +40,18 @@ pub struct Platform {
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Requirement {
     pub components: Option<Vec<String>>,
     pub hints: Option<Vec<String>>,
     pub version: Option<String>,
+    /// Attributes outside of the CPS schema, such as vendor extensions
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct ComponentFields {
     pub location: Option<String>,
     pub requires: Option<Vec<String>>,
@@ -44,7 +65,10 @@ pub struct ComponentFields {
     pub link_languages: Option<Vec<String>>,
     pub link_libraries: Option<Vec<String>>,
     pub link_location: Option<String>,
-    pub link_requires: Option<String>,
+    pub link_requires: Option<Vec<String>>,
+    /// Attributes outside of the CPS schema, such as vendor extensions
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 impl ComponentFields {
@@ -62,17 +86,54 @@ impl ComponentFields {
         }
         false
     }
+
+    /// Test if any location is set, either through an attribute or in some configuration
+    pub fn has_any_location(&self) -> bool {
+        self.location.is_some()
+            || self.link_location.is_some()
+            || self
+                .configurations
+                .iter()
+                .flat_map(|c| c.values())
+                .any(|config| config.location.is_some() || config.link_location.is_some())
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A component of a known type, or the raw JSON of one this crate can't represent
+///
+/// More ways to represent a component may be added, so matches need a wildcard arm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 #[allow(clippy::large_enum_variant)]
+#[non_exhaustive]
 pub enum MaybeComponent {
     Component(Component),
     Other(serde_json::Value),
 }
 
 impl MaybeComponent {
+    /// The CPS `type` of the component, e.g. `dylib`
+    pub fn type_name(&self) -> &str {
+        match self {
+            Self::Component(Component::Archive(_)) => "archive",
+            Self::Component(Component::Dylib(_)) => "dylib",
+            Self::Component(Component::Module(_)) => "module",
+            Self::Component(Component::Jar(_)) => "jar",
+            Self::Component(Component::Interface(_)) => "interface",
+            Self::Component(Component::Symbolic(_)) => "symbolic",
+            Self::Component(Component::Unknown) => "unknown",
+            Self::Other(value) => value["type"].as_str().unwrap_or("unknown"),
+        }
+    }
+
+    /// The fields of the component, or `None` if the component type is not known
+    pub fn fields(&self) -> Option<&ComponentFields> {
+        match self {
+            Self::Component(component) => component.fields(),
+            Self::Other(_) => None,
+        }
+    }
+
     pub fn from_dylib_location(location: &str) -> Self {
         Self::Component(Component::Dylib(ComponentFields {
             location: Some(location.to_string()),
@@ -88,9 +149,28 @@ impl MaybeComponent {
     }
 }
 
+/// A component by its CPS `type`
+///
+/// New component types may be added, so matches need a wildcard arm:
+///
+/// ```
+/// use cps_deps::cps::Component;
+///
+/// fn is_linked(component: &Component) -> bool {
+///     match component {
+///         Component::Archive(_) | Component::Dylib(_) => true,
+///         Component::Interface(_) | Component::Symbolic(_) => false,
+///         _ => false,
+///     }
+/// }
+///
+/// assert!(is_linked(&Component::Dylib(Default::default())));
+/// assert!(!is_linked(&Component::Jar(Default::default())));
+/// ```
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum Component {
     Archive(ComponentFields),
     Dylib(ComponentFields),
@@ -99,10 +179,62 @@ pub enum Component {
     Interface(ComponentFields),
     Symbolic(ComponentFields),
     #[default]
-    Unknwon,
+    #[serde(alias = "unknwon")]
+    Unknown,
+}
+
+/// The CPS types of the components this crate models, see [`Package::components_of_type`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentKind {
+    Archive,
+    Dylib,
+    Module,
+    Jar,
+    Interface,
+    Symbolic,
+}
+
+impl Component {
+    /// The kind of the component, or `None` if the component type is not known
+    pub fn kind(&self) -> Option<ComponentKind> {
+        match self {
+            Component::Archive(_) => Some(ComponentKind::Archive),
+            Component::Dylib(_) => Some(ComponentKind::Dylib),
+            Component::Module(_) => Some(ComponentKind::Module),
+            Component::Jar(_) => Some(ComponentKind::Jar),
+            Component::Interface(_) => Some(ComponentKind::Interface),
+            Component::Symbolic(_) => Some(ComponentKind::Symbolic),
+            Component::Unknown => None,
+        }
+    }
+
+    /// The fields of the component, or `None` if the component type is not known
+    pub fn fields(&self) -> Option<&ComponentFields> {
+        match self {
+            Component::Archive(fields)
+            | Component::Dylib(fields)
+            | Component::Module(fields)
+            | Component::Jar(fields)
+            | Component::Interface(fields)
+            | Component::Symbolic(fields) => Some(fields),
+            Component::Unknown => None,
+        }
+    }
+
+    fn fields_mut(&mut self) -> Option<&mut ComponentFields> {
+        match self {
+            Component::Archive(fields)
+            | Component::Dylib(fields)
+            | Component::Module(fields)
+            | Component::Jar(fields)
+            | Component::Interface(fields)
+            | Component::Symbolic(fields) => Some(fields),
+            Component::Unknown => None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum LanguageStringList {
     LanguageMap(HashMap<String, Vec<String>>),
@@ -113,10 +245,30 @@ impl LanguageStringList {
     pub fn any_language_map(list: Vec<String>) -> Self {
         Self::LanguageMap(HashMap::from([("*".to_string(), list)]))
     }
+
+    /// All values of the list, with the language-agnostic `*` entries first and the
+    /// remaining languages in sorted order
+    pub fn values(&self) -> Vec<&String> {
+        match self {
+            Self::List(list) => list.iter().collect(),
+            Self::LanguageMap(map) => {
+                let mut languages: Vec<_> = map.keys().collect();
+                languages.sort_by_key(|&language| (language != "*", language));
+                languages.into_iter().flat_map(|l| &map[l]).collect()
+            }
+        }
+    }
+
+    fn values_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::List(list) => list.iter_mut().collect(),
+            Self::LanguageMap(map) => map.values_mut().flatten().collect(),
+        }
+    }
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Configuration {
     pub location: Option<String>,
     pub requires: Option<Vec<String>>,
@@ -129,11 +281,11 @@ pub struct Configuration {
     pub link_languages: Option<Vec<String>>,
     pub link_libraries: Option<Vec<String>>,
     pub link_location: Option<String>,
-    pub link_requires: Option<String>,
+    pub link_requires: Option<Vec<String>>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Package {
     pub name: String,
     pub cps_version: String,
@@ -149,21 +301,172 @@ pub struct Package {
     pub default_components: Option<Vec<String>>,
     pub requires: Option<HashMap<String, Requirement>>,
     pub compat_version: Option<String>,
+    /// SPDX license expression of the package
+    pub license: Option<String>,
+    /// Attributes outside of the CPS schema, such as vendor extensions
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Print the package of a CPS file as a tree, or as JSON with `json`
+///
+/// With `strict` components of a type that is not part of CPS are an error. With `check_files`
+/// the component locations that don't exist are an error too, see
+/// [`Package::validate_locations`].
+pub fn parse_and_print_cps(
+    filepath: &Path,
+    json: bool,
+    strict: bool,
+    check_files: bool,
+) -> Result<()> {
+    let package = load_cps(filepath)?;
+    if strict {
+        package.validate_strict()?;
+    }
+    if check_files {
+        // An unknown prefix is left in place, so the locations under it are reported missing
+        let prefix = crate::flags::install_prefix(&package, filepath)
+            .unwrap_or_else(|| PathBuf::from(PREFIX_TOKEN));
+        let missing = package.validate_locations(&prefix);
+        for path in &missing {
+            eprintln!("Missing: {}", path.display());
+        }
+        anyhow::ensure!(
+            missing.is_empty(),
+            "{} location(s) of package `{}` don't exist",
+            missing.len(),
+            package.name
+        );
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&package)?);
+    } else {
+        print!("{}", package);
+    }
+    Ok(())
+}
+
+/// Labeled values of the attributes worth showing a reader, leaving out those that are not set
+fn summary<'a>(
+    attributes: impl IntoIterator<Item = (&'static str, Vec<&'a String>)>,
+) -> Vec<(&'static str, Vec<&'a String>)> {
+    attributes
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .collect()
+}
+
+fn values(list: &Option<Vec<String>>) -> Vec<&String> {
+    list.iter().flatten().collect()
+}
+
+fn language_values(list: &Option<LanguageStringList>) -> Vec<&String> {
+    list.iter().flat_map(LanguageStringList::values).collect()
+}
+
+impl ComponentFields {
+    /// The location, requirements and key flags of the component, see [`summary`]
+    fn summary(&self) -> Vec<(&'static str, Vec<&String>)> {
+        summary([
+            ("location", self.location.iter().collect()),
+            ("link_location", self.link_location.iter().collect()),
+            ("requires", values(&self.requires)),
+            ("link_requires", values(&self.link_requires)),
+            ("compile_features", values(&self.compile_features)),
+            ("definitions", language_values(&self.definitions)),
+            ("includes", language_values(&self.includes)),
+            ("compile_flags", language_values(&self.compile_flags)),
+            ("link_flags", values(&self.link_flags)),
+            ("link_libraries", values(&self.link_libraries)),
+        ])
+    }
 }
 
-pub fn parse_and_print_cps(filepath: &Path) -> Result<()> {
-    let file = File::open(filepath)?;
-    let reader = BufReader::new(file);
-    let package = Package::from_reader(reader)?;
+impl Configuration {
+    /// The location, requirements and key flags of the configuration, see [`summary`]
+    fn summary(&self) -> Vec<(&'static str, Vec<&String>)> {
+        summary([
+            ("location", self.location.iter().collect()),
+            ("link_location", self.link_location.iter().collect()),
+            ("requires", values(&self.requires)),
+            ("link_requires", values(&self.link_requires)),
+            ("compile_features", values(&self.compile_features)),
+            ("definitions", language_values(&self.definitions)),
+            ("includes", language_values(&self.includes)),
+            ("compile_flags", language_values(&self.compile_flags)),
+            ("link_flags", values(&self.link_flags)),
+            ("link_libraries", values(&self.link_libraries)),
+        ])
+    }
+}
 
-    dbg!(package);
+fn write_summary(
+    f: &mut fmt::Formatter<'_>,
+    indent: &str,
+    summary: Vec<(&'static str, Vec<&String>)>,
+) -> fmt::Result {
+    for (label, values) in summary {
+        let values: Vec<_> = values.into_iter().map(String::as_str).collect();
+        writeln!(f, "{}{}: {}", indent, label, values.join(" "))?;
+    }
     Ok(())
 }
 
+impl fmt::Display for MaybeComponent {
+    /// The type of the component followed by its attributes, one per indented line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "({})", self.type_name())?;
+        let Some(fields) = self.fields() else {
+            return Ok(());
+        };
+        write_summary(f, "    ", fields.summary())?;
+        if let Some(configurations) = &fields.configurations {
+            let mut names: Vec<_> = configurations.keys().collect();
+            names.sort();
+            for name in names {
+                writeln!(f, "    configuration {}", name)?;
+                write_summary(f, "      ", configurations[name].summary())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Package {
+    /// The name and version of the package, then each component in name order
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => writeln!(f, "{} {}", self.name, version)?,
+            None => writeln!(f, "{}", self.name)?,
+        }
+        if let Some(description) = &self.description {
+            writeln!(f, "  {}", description)?;
+        }
+        if let Some(license) = &self.license {
+            writeln!(f, "  license: {}", license)?;
+        }
+        if let Some(default_components) = &self.default_components {
+            writeln!(f, "  default components: {}", default_components.join(" "))?;
+        }
+        if let Some(requires) = &self.requires {
+            let mut names: Vec<_> = requires.keys().map(String::as_str).collect();
+            names.sort();
+            writeln!(f, "  requires: {}", names.join(" "))?;
+        }
+        let mut names: Vec<_> = self.components.keys().collect();
+        names.sort();
+        for name in names {
+            write!(f, "  {} {}", name, self.components[name])?;
+        }
+        Ok(())
+    }
+}
+
 impl FromStr for Package {
-    type Err = anyhow::Error;
+    type Err = Error;
 
-    fn from_str(data: &str) -> Result<Self> {
+    fn from_str(data: &str) -> Result<Self, Error> {
         let package: Package = serde_json::from_str(data)?;
         package.validate()?;
         Ok(package)
@@ -186,12 +489,266 @@ impl Default for Package {
             default_components: None,
             requires: None,
             compat_version: None,
+            license: None,
+            extra: HashMap::default(),
         }
     }
 }
 
+/// Replace the [`PREFIX_TOKEN`] in `value` with `prefix`
+fn resolve_prefix(value: &mut String, prefix: &str) {
+    if value.contains(PREFIX_TOKEN) {
+        *value = value.replace(PREFIX_TOKEN, prefix);
+    }
+}
+
 impl Package {
-    pub fn from_reader<R>(reader: R) -> Result<Self>
+    /// The name and fields of the components of type `kind`, in name order
+    ///
+    /// Components kept as raw JSON have no [`ComponentKind`] and are never returned, see
+    /// [`Package::exe_components`] for the `exe` ones.
+    pub fn components_of_type(
+        &self,
+        kind: ComponentKind,
+    ) -> impl Iterator<Item = (&str, &ComponentFields)> {
+        let mut components: Vec<_> = self
+            .components
+            .iter()
+            .filter_map(|(name, component)| match component {
+                MaybeComponent::Component(component) if component.kind() == Some(kind) => {
+                    Some((name.as_str(), component.fields()?))
+                }
+                _ => None,
+            })
+            .collect();
+        components.sort_by_key(|(name, _)| *name);
+        components.into_iter()
+    }
+
+    /// The name and raw JSON of the `exe` components, in name order, see
+    /// [`Package::components_of_type`] for the modeled ones
+    pub fn exe_components(&self) -> impl Iterator<Item = (&str, &serde_json::Value)> {
+        let mut components: Vec<_> = self
+            .components
+            .iter()
+            .filter_map(|(name, component)| match component {
+                MaybeComponent::Other(value) if component.type_name() == "exe" => {
+                    Some((name.as_str(), value))
+                }
+                _ => None,
+            })
+            .collect();
+        components.sort_by_key(|(name, _)| *name);
+        components.into_iter()
+    }
+
+    /// The name and component of each of the `default_components`, skipping names that are not
+    /// components of the package
+    pub fn default_components(&self) -> impl Iterator<Item = (&str, &MaybeComponent)> {
+        self.default_components
+            .iter()
+            .flatten()
+            .filter_map(|name| Some((name.as_str(), self.components.get(name)?)))
+    }
+
+    /// Combine the components and requirements of `other` into this package, e.g. to assemble
+    /// a core library and its plugins into one package
+    ///
+    /// The merged package keeps this package's name. A component or version defined by both
+    /// packages must be the same in each, requirements on the same package are combined, and
+    /// requirements of one package on the other are dropped.
+    pub fn merge(mut self, other: Package) -> Result<Package, Error> {
+        let (package, other_name) = (self.name.clone(), other.name.clone());
+        let conflict = |attribute: String| Error::MergeConflict {
+            package: package.clone(),
+            other: other_name.clone(),
+            attribute,
+        };
+        let version = match (&self.version, &other.version) {
+            (Some(version), Some(other_version)) if version != other_version => {
+                return Err(conflict(format!(
+                    "version `{}` and `{}`",
+                    version, other_version
+                )))
+            }
+            (version, other_version) => version.clone().or(other_version.clone()),
+        };
+
+        let components = &mut self.components;
+        for (name, component) in &other.components {
+            match components.get(name) {
+                Some(existing) if existing != component => {
+                    return Err(conflict(format!("component `{}`", name)))
+                }
+                Some(_) => {}
+                None => {
+                    components.insert(name.clone(), component.clone());
+                }
+            }
+        }
+
+        let mut requires = self.requires.take().unwrap_or_default();
+        for (name, requirement) in other.requires.iter().flatten() {
+            let Some(existing) = requires.get_mut(name) else {
+                requires.insert(name.clone(), requirement.clone());
+                continue;
+            };
+            match (&existing.version, &requirement.version) {
+                (Some(version), Some(other_version)) if version != other_version => {
+                    return Err(conflict(format!("requirement `{}`", name)))
+                }
+                (None, Some(other_version)) => existing.version = Some(other_version.clone()),
+                _ => {}
+            }
+            if let Some(other_components) = &requirement.components {
+                let components = existing.components.get_or_insert_with(Vec::new);
+                for component in other_components {
+                    if !components.contains(component) {
+                        components.push(component.clone());
+                    }
+                }
+            }
+        }
+        requires.remove(&self.name);
+        requires.remove(&other.name);
+
+        let mut default_components = self.default_components.take().unwrap_or_default();
+        for name in other.default_components.iter().flatten() {
+            if !default_components.contains(name) {
+                default_components.push(name.clone());
+            }
+        }
+
+        self.version = version;
+        self.requires = (!requires.is_empty()).then_some(requires);
+        self.default_components = (!default_components.is_empty()).then_some(default_components);
+        Ok(self)
+    }
+
+    /// Substitute `install_prefix` for the [`PREFIX_TOKEN`] in the `location`, `link_location`
+    /// and `includes` of every component and configuration
+    ///
+    /// The install prefix of an installed package can be found with
+    /// [`crate::flags::install_prefix`].
+    pub fn resolve_paths(&mut self, install_prefix: &Path) {
+        let prefix = install_prefix.to_string_lossy();
+        for component in self.components.values_mut() {
+            let fields = match component {
+                MaybeComponent::Component(component) => component.fields_mut(),
+                MaybeComponent::Other(value) => {
+                    for key in ["location", "link_location"] {
+                        if let Some(serde_json::Value::String(path)) = value.get_mut(key) {
+                            resolve_prefix(path, &prefix);
+                        }
+                    }
+                    None
+                }
+            };
+            let Some(fields) = fields else {
+                continue;
+            };
+            for path in [&mut fields.location, &mut fields.link_location]
+                .into_iter()
+                .flatten()
+            {
+                resolve_prefix(path, &prefix);
+            }
+            for include in fields.includes.iter_mut().flat_map(|i| i.values_mut()) {
+                resolve_prefix(include, &prefix);
+            }
+            for configuration in fields
+                .configurations
+                .iter_mut()
+                .flat_map(|c| c.values_mut())
+            {
+                for path in [
+                    &mut configuration.location,
+                    &mut configuration.link_location,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    resolve_prefix(path, &prefix);
+                }
+                for include in configuration
+                    .includes
+                    .iter_mut()
+                    .flat_map(|i| i.values_mut())
+                {
+                    resolve_prefix(include, &prefix);
+                }
+            }
+        }
+    }
+
+    /// The `location` and `link_location` paths of the components and their configurations that
+    /// don't exist once [`PREFIX_TOKEN`] is replaced by `install_prefix`, in component name order
+    ///
+    /// Unlike [`Package::validate`] this checks the package against the filesystem, e.g. to find
+    /// a stale cps file of a package that was moved.
+    pub fn validate_locations(&self, install_prefix: &Path) -> Vec<PathBuf> {
+        let mut package = self.clone();
+        package.resolve_paths(install_prefix);
+
+        let mut names: Vec<_> = package.components.keys().collect();
+        names.sort();
+        let mut missing = Vec::new();
+        for name in names {
+            let locations: Vec<&String> = match &package.components[name] {
+                MaybeComponent::Component(component) => component
+                    .fields()
+                    .into_iter()
+                    .flat_map(|fields| {
+                        let configurations = fields.configurations.iter().flat_map(|c| {
+                            let mut names: Vec<_> = c.keys().collect();
+                            names.sort();
+                            names.into_iter().map(|name| &c[name])
+                        });
+                        [&fields.location, &fields.link_location]
+                            .into_iter()
+                            .chain(configurations.flat_map(|configuration| {
+                                [&configuration.location, &configuration.link_location]
+                            }))
+                            .flatten()
+                    })
+                    .collect(),
+                MaybeComponent::Other(value) => ["location", "link_location"]
+                    .into_iter()
+                    .filter_map(|key| match value.get(key) {
+                        Some(serde_json::Value::String(path)) => Some(path),
+                        _ => None,
+                    })
+                    .collect(),
+            };
+            missing.extend(
+                locations
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .filter(|path| !path.exists()),
+            );
+        }
+        missing
+    }
+
+    /// Parse like [`Package::from_str`] but fail on top-level attributes that are not part of
+    /// CPS, e.g. a misspelled `versoin`, instead of keeping them in `extra`
+    ///
+    /// Vendor extensions, whose names start with `x-`, are still allowed.
+    pub fn from_str_strict(data: &str) -> Result<Self, Error> {
+        let package = Self::from_str(data)?;
+        let mut unknown: Vec<_> = package
+            .extra
+            .keys()
+            .filter(|key| !key.starts_with("x-"))
+            .collect();
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => Err(Error::UnknownField(key.to_string())),
+            None => Ok(package),
+        }
+    }
+
+    pub fn from_reader<R>(reader: R) -> Result<Self, Error>
     where
         R: std::io::Read,
     {
@@ -200,111 +757,763 @@ impl Package {
         Ok(package)
     }
 
+    /// Load the packages required by the default components from `<search path>/<name>.cps`,
+    /// transitively, and accumulate their flags
+    pub fn resolve_requires(&self, search_paths: &[PathBuf]) -> Result<ResolvedFlags> {
+        crate::flags::resolve_requires(self, search_paths)
+    }
+
+    /// Validate like [`Package::validate`] and also reject components of a type that is not
+    /// part of CPS, such as a misspelled `dlib`
+    pub fn validate_strict(&self) -> Result<(), Error> {
+        self.validate()?;
+        let mut names: Vec<_> = self.components.keys().collect();
+        names.sort();
+        for name in names {
+            let component = &self.components[name];
+            let known = match component {
+                MaybeComponent::Component(Component::Unknown) => false,
+                MaybeComponent::Component(_) => true,
+                MaybeComponent::Other(_) => OTHER_COMPONENT_TYPES.contains(&component.type_name()),
+            };
+            if !known {
+                return Err(Error::UnknownComponentType {
+                    component: name.clone(),
+                    type_name: component.type_name().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Test if the package describes a single one of its `configurations`
+    ///
+    /// That is the case when it names its `configuration`, or when it lists `configurations`
+    /// but its built components have a location without any per-configuration data.
+    pub fn is_configuration_specific(&self) -> bool {
+        if self.configuration.is_some() {
+            return true;
+        }
+        if self.configurations.as_ref().is_none_or(Vec::is_empty) {
+            return false;
+        }
+        let fields: Vec<_> = self
+            .components
+            .values()
+            .filter_map(|c| c.fields())
+            .collect();
+        fields.iter().all(|fields| fields.configurations.is_none())
+            && fields.iter().any(|fields| fields.location.is_some())
+    }
+
     /// Used by deserialization functions to validate CPS schema rules
-    pub fn validate(&self) -> Result<()> {
+    pub fn validate(&self) -> Result<(), Error> {
         if self.cps_version != CPS_VERSION {
-            bail!("Unsupported CPS version: {}", self.cps_version);
+            return Err(Error::UnsupportedCpsVersion(self.cps_version.clone()));
         }
-        for (name, component) in self.components.iter() {
-            match component {
-                MaybeComponent::Component(Component::Archive(fields)) => {
-                    if !fields.has_location() {
-                        bail!("Component `{}` is missing attribute `location`", name);
-                    }
+        if let (Some(compat_version), Some(version)) = (&self.compat_version, &self.version) {
+            let schema = match &self.version_schema {
+                Some(schema) => schema.parse().ok(),
+                None => Some(VersionSchema::default()),
+            };
+            let ordering =
+                schema.and_then(|schema| version::compare(compat_version, version, schema));
+            if ordering == Some(Ordering::Greater) {
+                return Err(Error::CompatVersionTooNew {
+                    compat_version: compat_version.clone(),
+                    version: version.clone(),
+                });
+            }
+        }
+        if self.is_configuration_specific() {
+            let configuration =
+                self.configuration
+                    .as_ref()
+                    .ok_or_else(|| Error::MissingConfiguration {
+                        package: self.name.clone(),
+                    })?;
+            if let Some(configurations) = &self.configurations {
+                if !configurations.contains(configuration) {
+                    return Err(Error::UnknownConfiguration {
+                        package: self.name.clone(),
+                        configuration: configuration.clone(),
+                    });
                 }
-                MaybeComponent::Component(Component::Dylib(fields)) => {
-                    if !fields.has_location() {
-                        bail!("Component `{}` is missing attribute `location`", name);
-                    }
+            }
+        }
+        for (name, component) in self.components.iter() {
+            if let MaybeComponent::Component(
+                Component::Archive(fields)
+                | Component::Dylib(fields)
+                | Component::Module(fields)
+                | Component::Jar(fields),
+            ) = component
+            {
+                if !fields.has_location() {
+                    return Err(Error::MissingLocation {
+                        component: name.clone(),
+                    });
                 }
-                MaybeComponent::Component(Component::Module(fields)) => {
-                    if !fields.has_location() {
-                        bail!("Component `{}` is missing attribute `location`", name);
-                    }
+            }
+            if let MaybeComponent::Component(Component::Interface(fields)) = component {
+                if fields.has_any_location() {
+                    return Err(Error::UnexpectedLocation {
+                        component: name.clone(),
+                    });
                 }
-                MaybeComponent::Component(Component::Jar(fields)) => {
-                    if !fields.has_location() {
-                        bail!("Component `{}` is missing attribute `location`", name);
-                    }
+            }
+            if let MaybeComponent::Component(Component::Symbolic(fields)) = component {
+                let has_target = [&fields.requires, &fields.link_requires]
+                    .into_iter()
+                    .flatten()
+                    .any(|targets| !targets.is_empty());
+                if !has_target {
+                    return Err(Error::DanglingSymbolic {
+                        component: name.clone(),
+                    });
                 }
-                _ => {}
             }
         }
         Ok(())
     }
 }
 
-#[test]
-fn test_parse_sample_cps() -> Result<()> {
-    // cps_version was manually added: https://github.com/cps-org/cps/issues/57
-    let sample_cps = r#"{
-    "name": "sample",
-    "description": "Sample CPS",
-    "license": "BSD",
-    "version": "1.2.0",
-    "compat_version": "0.8.0",
-    "cps_version": "0.11.0",
-    "platform": {
-        "isa": "x86_64",
-        "kernel": "linux",
-        "c_runtime_vendor": "gnu",
-        "c_runtime_version": "2.20",
-        "jvm_version": "1.6"
-    },
-    "configurations": [ "optimized", "debug" ],
-    "default_components": [ "sample" ],
-    "components": {
-        "sample-core": {
-        "type": "interface",
-        "definitions": [ "SAMPLE" ],
-        "includes": [ "@prefix@/include" ]
-        },
-        "sample": {
-        "type": "interface",
-        "configurations": {
-            "shared": {
-            "requires": [ ":sample-shared" ]
+/// Builds a [`Package`] one component at a time, validating it once complete
+#[derive(Debug, Default)]
+pub struct PackageBuilder {
+    package: Package,
+}
+
+impl PackageBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            package: Package {
+                name: name.to_string(),
+                ..Package::default()
             },
-            "static": {
-            "requires": [ ":sample-static" ]
-            }
         }
-        },
-        "sample-shared": {
-        "type": "dylib",
-        "requires": [ ":sample-core" ],
-        "configurations": {
-            "optimized": {
-            "location": "@prefix@/lib64/libsample.so.1.2.0"
-            },
-            "debug": {
-            "location": "@prefix@/lib64/libsample_d.so.1.2.0"
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.package.version = Some(version.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.package.description = Some(description.to_string());
+        self
+    }
+
+    /// Require the package `name`, the key the components' requires refer to
+    pub fn require(mut self, name: &str, requirement: Requirement) -> Self {
+        self.package
+            .requires
+            .get_or_insert_with(HashMap::new)
+            .insert(name.to_string(), requirement);
+        self
+    }
+
+    pub fn add_component(mut self, name: &str, component: Component) -> Self {
+        self.package
+            .components
+            .insert(name.to_string(), MaybeComponent::Component(component));
+        self
+    }
+
+    pub fn add_dylib(self, name: &str, location: &str) -> Self {
+        self.add_component(
+            name,
+            Component::Dylib(ComponentFields {
+                location: Some(location.to_string()),
+                ..ComponentFields::default()
+            }),
+        )
+    }
+
+    pub fn add_archive(self, name: &str, location: &str) -> Self {
+        self.add_component(
+            name,
+            Component::Archive(ComponentFields {
+                location: Some(location.to_string()),
+                ..ComponentFields::default()
+            }),
+        )
+    }
+
+    pub fn add_interface(self, name: &str, fields: ComponentFields) -> Self {
+        self.add_component(name, Component::Interface(fields))
+    }
+
+    pub fn default_components(mut self, names: &[&str]) -> Self {
+        self.package.default_components = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// The package, if it is valid
+    pub fn build(self) -> Result<Package, Error> {
+        self.package.validate()?;
+        Ok(self.package)
+    }
+}
+
+/// An index of the cps files found in a list of directories by package name, the CPS
+/// counterpart of pkg-config's `PKG_CONFIG_PATH` search
+#[derive(Debug, Default)]
+pub struct Registry {
+    search_paths: Vec<PathBuf>,
+    index: HashMap<String, PathBuf>,
+}
+
+impl Registry {
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        let mut registry = Self {
+            search_paths,
+            index: HashMap::new(),
+        };
+        registry.refresh();
+        registry
+    }
+
+    /// Registry over the directories listed in the `CPS_PATH` environment variable
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var_os("CPS_PATH")
+                .map(|paths| std::env::split_paths(&paths).collect())
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Rebuild the index from the cps files currently in the search paths
+    ///
+    /// When several files declare the same package name the one in the earliest search path
+    /// wins. Files that fail to parse are not indexed.
+    pub fn refresh(&mut self) {
+        self.index.clear();
+        for dir in &self.search_paths {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && path.extension().is_some_and(|ex| ex == "cps"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                if let Ok(package) = load_cps(&path) {
+                    self.index.entry(package.name).or_insert(path);
+                }
             }
         }
-        },
-        "sample-static": {
-        "type": "archive",
-        "definitions": [ "SAMPLE_STATIC" ],
-        "requires": [ ":sample-core" ],
-        "configurations": {
-            "optimized": {
-            "location": "@prefix@/lib64/libsample.a"
-            },
-            "debug": {
-            "location": "@prefix@/lib64/libsample_d.a"
+    }
+
+    /// Path of the cps file declaring the package `name`
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.index.get(name).map(PathBuf::as_path)
+    }
+
+    /// Names of all indexed packages
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    pub fn find(&self, name: &str) -> Option<Package> {
+        load_cps(self.path(name)?).ok()
+    }
+}
+
+/// Read and validate the package of a CPS file
+pub fn load_cps(path: &Path) -> Result<Package, Error> {
+    Package::from_reader(BufReader::new(File::open(path)?))
+}
+
+#[test]
+fn test_registry() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let package = |name: &str| {
+        format!(
+            r#"{{ "name": "{}", "cps_version": "0.11.0", "components": {{}} }}"#,
+            name
+        )
+    };
+    dir.write("first/foo.cps", &package("foo"));
+    // the file name doesn't need to match the package name
+    dir.write("first/bar-1.0.cps", &package("bar"));
+    dir.write("first/broken.cps", "{");
+    dir.write("second/foo.cps", &package("foo"));
+
+    let mut registry = Registry::new(vec![dir.path().join("first"), dir.path().join("second")]);
+    let mut names: Vec<_> = registry.names().collect();
+    names.sort();
+    assert_eq!(names, vec!["bar", "foo"]);
+    assert_eq!(
+        registry.find("bar").map(|p| p.name),
+        Some("bar".to_string())
+    );
+    assert_eq!(
+        registry.path("foo"),
+        Some(dir.path().join("first/foo.cps").as_path())
+    );
+    assert!(registry.find("baz").is_none());
+
+    dir.write("second/baz.cps", &package("baz"));
+    assert!(registry.find("baz").is_none());
+    registry.refresh();
+    assert_eq!(
+        registry.find("baz").map(|p| p.name),
+        Some("baz".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_validate_error_variants() {
+    let unsupported = r#"{ "name": "foo", "cps_version": "0.8.0", "components": {} }"#;
+    assert!(matches!(
+        Package::from_str(unsupported),
+        Err(Error::UnsupportedCpsVersion(version)) if version == "0.8.0"
+    ));
+
+    let missing_location = r#"{
+        "name": "foo",
+        "cps_version": "0.11.0",
+        "components": { "foo": { "type": "dylib" } }
+    }"#;
+    assert!(matches!(
+        Package::from_str(missing_location),
+        Err(Error::MissingLocation { component }) if component == "foo"
+    ));
+    assert!(matches!(Package::from_str("{"), Err(Error::Json(_))));
+}
+
+#[test]
+fn test_validate_symbolic() {
+    let package = |alias: &str| {
+        format!(
+            r#"{{
+                "name": "foo",
+                "cps_version": "0.11.0",
+                "components": {{
+                    "foo": {{ "type": "interface", "includes": ["/usr/include/foo"] }},
+                    "foo-alias": {}
+                }}
+            }}"#,
+            alias
+        )
+    };
+
+    assert!(Package::from_str(&package(r#"{ "type": "symbolic", "requires": [":foo"] }"#)).is_ok());
+    assert!(Package::from_str(&package(
+        r#"{ "type": "symbolic", "link_requires": [":foo"] }"#
+    ))
+    .is_ok());
+    for dangling in [
+        r#"{ "type": "symbolic" }"#,
+        r#"{ "type": "symbolic", "requires": [] }"#,
+    ] {
+        assert!(matches!(
+            Package::from_str(&package(dangling)),
+            Err(Error::DanglingSymbolic { component }) if component == "foo-alias"
+        ));
+    }
+}
+
+#[test]
+fn test_validate_interface() {
+    let package = |interface: &str| {
+        format!(
+            r#"{{
+                "name": "foo",
+                "cps_version": "0.11.0",
+                "components": {{ "foo": {} }}
+            }}"#,
+            interface
+        )
+    };
+
+    assert!(Package::from_str(&package(
+        r#"{ "type": "interface", "includes": ["/usr/include/foo"] }"#
+    ))
+    .is_ok());
+    for stray in [
+        r#"{ "type": "interface", "location": "/usr/lib/libfoo.so" }"#,
+        r#"{ "type": "interface", "link_location": "/usr/lib/libfoo.so" }"#,
+        r#"{ "type": "interface", "configurations": { "release": { "location": "/usr/lib/libfoo.so" } } }"#,
+    ] {
+        assert!(matches!(
+            Package::from_str(&package(stray)),
+            Err(Error::UnexpectedLocation { component }) if component == "foo"
+        ));
+    }
+}
+
+#[test]
+fn test_validate_configuration_specific() -> Result<()> {
+    let package = |configuration: &str| {
+        format!(
+            r#"{{
+                "name": "foo",
+                "cps_version": "0.11.0",
+                "configurations": ["release", "debug"],
+                {}
+                "components": {{ "foo": {{ "type": "dylib", "location": "/usr/lib/libfoo.so" }} }}
+            }}"#,
+            configuration
+        )
+    };
+
+    let specific = Package::from_str(&package(r#""configuration": "release","#))?;
+    assert!(specific.is_configuration_specific());
+    assert!(matches!(
+        Package::from_str(&package("")),
+        Err(Error::MissingConfiguration { package }) if package == "foo"
+    ));
+    assert!(matches!(
+        Package::from_str(&package(r#""configuration": "minsize","#)),
+        Err(Error::UnknownConfiguration { configuration, .. }) if configuration == "minsize"
+    ));
+
+    let sample = Package::from_str(include_str!("../testdata/sample.cps"))?;
+    assert!(!sample.is_configuration_specific());
+    Ok(())
+}
+
+#[test]
+fn test_validate_compat_version() {
+    let package = |compat_version: &str, version: &str, schema: &str| {
+        format!(
+            r#"{{
+                "name": "foo",
+                "cps_version": "0.11.0",
+                "version": "{}",
+                "compat_version": "{}",
+                {}
+                "components": {{}}
+            }}"#,
+            version, compat_version, schema
+        )
+    };
+
+    assert!(Package::from_str(&package("1.0", "1.2", "")).is_ok());
+    assert!(Package::from_str(&package("1.0.0", "1.0", "")).is_ok());
+    assert!(matches!(
+        Package::from_str(&package("2.0", "1.0", "")),
+        Err(Error::CompatVersionTooNew { compat_version, version })
+            if compat_version == "2.0" && version == "1.0"
+    ));
+    assert!(matches!(
+        Package::from_str(&package("1:1.0", "2.0", r#""version_schema": "rpm","#)),
+        Err(Error::CompatVersionTooNew { .. })
+    ));
+    // Versions of a schema that can't be compared aren't checked
+    assert!(Package::from_str(&package("2.0", "1.0", r#""version_schema": "custom","#)).is_ok());
+}
+
+#[test]
+fn test_validate_locations() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    dir.write("lib/libfoo.so", "");
+    let package = Package::from_str(
+        r#"{
+            "name": "foo",
+            "cps_version": "0.11.0",
+            "components": {
+                "foo": { "type": "dylib", "location": "@prefix@/lib/libfoo.so" },
+                "foo-static": { "type": "archive", "location": "@prefix@/lib/libfoo.a" },
+                "headers": { "type": "interface", "includes": ["@prefix@/include/missing"] }
             }
-        }
-        },
-        "sample-tool": {
-        "type": "exe",
-        "location": "@prefix@/bin/sample-tool"
+        }"#,
+    )?;
+
+    assert_eq!(
+        package.validate_locations(dir.path()),
+        vec![dir.path().join("lib/libfoo.a")]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resolve_paths() -> Result<()> {
+    let mut package = Package::from_str(include_str!("../testdata/sample.cps"))?;
+    package.resolve_paths(Path::new("/opt/sample"));
+
+    let fields = |name: &str| {
+        package.components[name]
+            .fields()
+            .cloned()
+            .unwrap_or_default()
+    };
+    let location = |name: &str, configuration: &str| {
+        fields(name).configurations.unwrap_or_default()[configuration]
+            .location
+            .clone()
+    };
+    assert_eq!(
+        location("sample-shared", "optimized").as_deref(),
+        Some("/opt/sample/lib64/libsample.so.1.2.0")
+    );
+    assert_eq!(
+        location("sample-static", "debug").as_deref(),
+        Some("/opt/sample/lib64/libsample_d.a")
+    );
+    assert_eq!(
+        fields("sample-java").location.as_deref(),
+        Some("/opt/sample/share/java/sample.jar")
+    );
+    assert_eq!(
+        fields("sample-core").includes,
+        Some(LanguageStringList::List(vec![
+            "/opt/sample/include".to_string()
+        ]))
+    );
+    assert_eq!(
+        match &package.components["sample-tool"] {
+            MaybeComponent::Other(value) => value["location"].as_str(),
+            MaybeComponent::Component(_) => None,
         },
-        "sample-java": {
-        "type": "jar",
-        "location": "@prefix@/share/java/sample.jar"
-        }
-    }
-}"#;
+        Some("/opt/sample/bin/sample-tool")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_components_of_type() -> Result<()> {
+    let package = Package::from_str(include_str!("../testdata/sample.cps"))?;
+
+    let dylibs: Vec<_> = package.components_of_type(ComponentKind::Dylib).collect();
+    assert_eq!(dylibs.len(), 1);
+    assert_eq!(dylibs[0].0, "sample-shared");
+    assert_eq!(dylibs[0].1.requires, Some(vec![":sample-core".to_string()]));
+
+    let interfaces: Vec<_> = package
+        .components_of_type(ComponentKind::Interface)
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(interfaces, ["sample", "sample-core"]);
+    assert_eq!(package.components_of_type(ComponentKind::Module).count(), 0);
+
+    let exes: Vec<_> = package
+        .exe_components()
+        .map(|(name, value)| (name, value["location"].as_str()))
+        .collect();
+    assert_eq!(exes, [("sample-tool", Some("@prefix@/bin/sample-tool"))]);
+
+    let defaults: Vec<_> = package
+        .default_components()
+        .map(|(name, component)| (name, component.type_name()))
+        .collect();
+    assert_eq!(defaults, [("sample", "interface")]);
+    Ok(())
+}
+
+#[test]
+fn test_merge() -> Result<()> {
+    let core = PackageBuilder::new("foo")
+        .version("1.0")
+        .require("bar", Requirement::default())
+        .add_dylib("foo", "/usr/lib/libfoo.so")
+        .default_components(&["foo"])
+        .build()?;
+    let plugins = PackageBuilder::new("foo-plugins")
+        .version("1.0")
+        .require("foo", Requirement::default())
+        .add_dylib("foo-gl", "/usr/lib/libfoo-gl.so")
+        .default_components(&["foo-gl"])
+        .build()?;
+
+    let merged = core.clone().merge(plugins)?;
+    assert_eq!(merged.name, "foo");
+    assert_eq!(merged.version.as_deref(), Some("1.0"));
+    let mut components: Vec<_> = merged.components.keys().collect();
+    components.sort();
+    assert_eq!(components, ["foo", "foo-gl"]);
+    assert_eq!(
+        merged.default_components,
+        Some(vec!["foo".to_string(), "foo-gl".to_string()])
+    );
+    let requires: Vec<_> = merged.requires.iter().flatten().map(|(n, _)| n).collect();
+    assert_eq!(requires, ["bar"]);
+
+    let conflicting = PackageBuilder::new("foo-other")
+        .add_dylib("foo", "/opt/lib/libfoo.so")
+        .build()?;
+    assert!(matches!(
+        core.merge(conflicting),
+        Err(Error::MergeConflict { attribute, .. }) if attribute == "component `foo`"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_display_sample_cps() -> Result<()> {
+    let package = Package::from_str(include_str!("../testdata/sample.cps"))?;
+    assert_eq!(
+        package.to_string(),
+        "\
+sample 1.2.0
+  Sample CPS
+  license: BSD
+  default components: sample
+  sample (interface)
+    configuration shared
+      requires: :sample-shared
+    configuration static
+      requires: :sample-static
+  sample-core (interface)
+    definitions: SAMPLE
+    includes: @prefix@/include
+  sample-java (jar)
+    location: @prefix@/share/java/sample.jar
+  sample-shared (dylib)
+    requires: :sample-core
+    configuration debug
+      location: @prefix@/lib64/libsample_d.so.1.2.0
+    configuration optimized
+      location: @prefix@/lib64/libsample.so.1.2.0
+  sample-static (archive)
+    requires: :sample-core
+    definitions: SAMPLE_STATIC
+    configuration debug
+      location: @prefix@/lib64/libsample_d.a
+    configuration optimized
+      location: @prefix@/lib64/libsample.a
+  sample-tool (exe)
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_load_cps() -> Result<()> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/sample.cps");
+    let package = load_cps(&path)?;
+    assert_eq!(package.name, "sample");
+    assert_eq!(package.version.as_deref(), Some("1.2.0"));
+    assert!(matches!(
+        load_cps(&path.with_file_name("missing.cps")),
+        Err(Error::Io(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_package_builder() -> Result<()> {
+    let package = PackageBuilder::new("fcl")
+        .version("0.7.0")
+        .description("Flexible Collision Library")
+        .require("ccd", Requirement::default())
+        .require("eigen3", Requirement::default())
+        .require("octomap", Requirement::default())
+        .add_dylib("fcl", "/usr/lib/x86_64-linux-gnu/libfcl.so")
+        .add_interface(
+            "fcl-headers",
+            ComponentFields {
+                requires: Some(vec![
+                    ":fcl".to_string(),
+                    "ccd".to_string(),
+                    "eigen3".to_string(),
+                    "octomap".to_string(),
+                ]),
+                compile_features: Some(vec!["c++11".to_string()]),
+                includes: Some(LanguageStringList::any_language_map(vec![
+                    "/usr/include".to_string()
+                ])),
+                ..ComponentFields::default()
+            },
+        )
+        .default_components(&["fcl-headers"])
+        .build()?;
+    package.validate()?;
+    assert_eq!(package.name, "fcl");
+    assert_eq!(package.cps_version, CPS_VERSION);
+    assert_eq!(package.components["fcl"].type_name(), "dylib");
+    assert_eq!(package.components["fcl-headers"].type_name(), "interface");
+    assert_eq!(package.requires.map(|requires| requires.len()), Some(3));
+
+    assert!(matches!(
+        PackageBuilder::new("foo")
+            .add_component("foo", Component::Archive(ComponentFields::default()))
+            .build(),
+        Err(Error::MissingLocation { component }) if component == "foo"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_clone_sample_cps() -> Result<()> {
+    let package = Package::from_str(include_str!("../testdata/sample.cps"))?;
+    let mut copy = package.clone();
+    assert_eq!(copy, package);
+
+    copy.components.remove("sample-tool");
+    assert_ne!(copy, package);
+    Ok(())
+}
+
+#[test]
+fn test_validate_unknown_component_type() -> Result<()> {
+    let package = |type_name: &str| {
+        format!(
+            r#"{{
+                "name": "foo",
+                "cps_version": "0.11.0",
+                "components": {{ "foo": {{ "type": "{}", "location": "/usr/lib/libfoo.so" }} }}
+            }}"#,
+            type_name
+        )
+    };
+
+    // lenient validation accepts types from newer versions of CPS
+    let typo = Package::from_str(&package("dlib"))?;
+    assert!(matches!(
+        typo.validate_strict(),
+        Err(Error::UnknownComponentType { component, type_name })
+            if component == "foo" && type_name == "dlib"
+    ));
+    let unknown = Package::from_str(&package("unknwon"))?;
+    assert_eq!(unknown.components["foo"].type_name(), "unknown");
+    assert!(matches!(
+        unknown.validate_strict(),
+        Err(Error::UnknownComponentType { type_name, .. }) if type_name == "unknown"
+    ));
+
+    Package::from_str(&package("dylib"))?.validate_strict()?;
+    Package::from_str(include_str!("../testdata/sample.cps"))?.validate_strict()?;
+    Ok(())
+}
+
+#[test]
+fn test_from_str_strict() -> Result<()> {
+    let misspelled = r#"{
+        "name": "foo",
+        "cps_version": "0.11.0",
+        "versoin": "1.0",
+        "components": {}
+    }"#;
+    assert!(Package::from_str(misspelled).is_ok());
+    assert!(matches!(
+        Package::from_str_strict(misspelled),
+        Err(Error::UnknownField(field)) if field == "versoin"
+    ));
+
+    let extended = r#"{
+        "name": "foo",
+        "cps_version": "0.11.0",
+        "version": "1.0",
+        "x-pkgconfig-conflicts": [],
+        "components": {}
+    }"#;
+    Package::from_str_strict(extended)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_sample_cps() -> Result<()> {
+    // cps_version was manually added: https://github.com/cps-org/cps/issues/57
+    let sample_cps = include_str!("../testdata/sample.cps");
 
     Package::from_str(sample_cps)?;
     Ok(())
@@ -0,0 +1,140 @@
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::cps::Package;
+use crate::generate_from_pkg_config::{convert_all, GenerateOptions};
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn diff_at(path: &str, existing: &Value, regenerated: &Value, diffs: &mut Vec<String>) {
+    match (existing, regenerated) {
+        (Value::Object(existing), Value::Object(regenerated)) => {
+            let mut keys: Vec<_> = existing.keys().chain(regenerated.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = join_path(path, key);
+                match (existing.get(key), regenerated.get(key)) {
+                    (Some(existing), Some(regenerated)) => {
+                        diff_at(&path, existing, regenerated, diffs)
+                    }
+                    (Some(existing), None) => diffs.push(format!("- {}: {}", path, existing)),
+                    (None, Some(regenerated)) => diffs.push(format!("+ {}: {}", path, regenerated)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (existing, regenerated) if existing != regenerated => {
+            diffs.push(format!("~ {}: {} -> {}", path, existing, regenerated))
+        }
+        _ => {}
+    }
+}
+
+/// Field-level differences between two JSON values, ignoring the order of object keys
+///
+/// Each line is prefixed by `-` for a field only in `existing`, `+` for a field only in
+/// `regenerated`, and `~` for a field whose value changed.
+pub fn diff_values(existing: &Value, regenerated: &Value) -> Vec<String> {
+    let mut diffs = Vec::new();
+    diff_at("", existing, regenerated, &mut diffs);
+    diffs
+}
+
+/// Regenerate a package from a pc file and compare it structurally to an existing cps file
+pub fn diff_pkg_config_cps(
+    pc_filepath: &Path,
+    cps_filepath: &Path,
+    options: &GenerateOptions,
+) -> Result<Vec<String>> {
+    let existing = Package::from_str(&std::fs::read_to_string(cps_filepath)?)?;
+    let (_, regenerated) = convert_all(&[pc_filepath.to_path_buf()], options)
+        .pop()
+        .expect("one result per pc file");
+    Ok(diff_values(
+        &serde_json::to_value(existing)?,
+        &serde_json::to_value(regenerated?)?,
+    ))
+}
+
+/// Print the differences between an existing cps file and the package regenerated from its pc
+/// file, failing if there are any
+pub fn print_diff(
+    pc_filepath: &Path,
+    cps_filepath: &Path,
+    options: &GenerateOptions,
+) -> Result<()> {
+    let diffs = diff_pkg_config_cps(pc_filepath, cps_filepath, options)?;
+    if diffs.is_empty() {
+        return Ok(());
+    }
+    for diff in &diffs {
+        println!("{}", diff);
+    }
+    bail!(
+        "`{}` is out of date with `{}`",
+        cps_filepath.display(),
+        pc_filepath.display()
+    )
+}
+
+#[test]
+fn test_diff_pkg_config_cps() -> Result<()> {
+    use crate::generate_from_pkg_config::generate_one;
+
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/opt/foo/include\n",
+    );
+    let cps = dir.path().join("foo.cps");
+    let options = GenerateOptions::default();
+    generate_one(&pc, &cps, &options)?;
+
+    assert_eq!(
+        diff_pkg_config_cps(&pc, &cps, &options)?,
+        Vec::<String>::new()
+    );
+    print_diff(&pc, &cps, &options)?;
+
+    dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.1\nCflags: -I/opt/foo/include\n",
+    );
+    assert_eq!(
+        diff_pkg_config_cps(&pc, &cps, &options)?,
+        vec![r#"~ version: "1.0" -> "1.1""#.to_string()]
+    );
+    assert!(print_diff(&pc, &cps, &options).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_diff_values() {
+    let existing = serde_json::json!({
+        "name": "foo",
+        "components": { "foo": { "type": "interface", "includes": ["/a"] } },
+        "description": "stale",
+    });
+    let regenerated = serde_json::json!({
+        "components": { "foo": { "includes": ["/b"], "type": "interface" } },
+        "name": "foo",
+        "version": "1.0",
+    });
+    assert_eq!(
+        diff_values(&existing, &regenerated),
+        vec![
+            r#"~ components.foo.includes: ["/a"] -> ["/b"]"#,
+            r#"- description: "stale""#,
+            r#"+ version: "1.0""#,
+        ]
+    );
+}
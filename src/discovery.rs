@@ -0,0 +1,654 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use ignore::{WalkBuilder, WalkState};
+
+use crate::pkg_config::PkgConfigFile;
+use crate::Error;
+
+/// Directories searched for pc files when no search directories are given
+///
+/// They are searched recursively, so their `pkgconfig` and multiarch subdirectories are
+/// included. The directories that are the same after resolving symlinks, like `/lib` and
+/// `/usr/lib` on merged-usr systems, are only searched once.
+pub const DEFAULT_SEARCH_DIRS: &[&str] = &[
+    "/usr/lib",
+    "/usr/lib64",
+    "/usr/share",
+    "/usr/local/lib",
+    "/usr/local/lib64",
+    "/usr/local/share",
+    "/lib",
+    "/lib64",
+];
+
+fn split_paths(paths: &OsStr) -> impl Iterator<Item = PathBuf> + '_ {
+    std::env::split_paths(paths).filter(|path| !path.as_os_str().is_empty())
+}
+
+/// Search directories with the semantics pkg-config gives its environment variables:
+/// the `pkg_config_path` directories are searched first, followed by `pkg_config_libdir` if
+/// set, which replaces the default search directories
+pub fn search_dirs_from(
+    pkg_config_path: Option<&OsStr>,
+    pkg_config_libdir: Option<&OsStr>,
+) -> Vec<PathBuf> {
+    let defaults: Vec<_> = match pkg_config_libdir {
+        Some(libdir) => split_paths(libdir).collect(),
+        None => DEFAULT_SEARCH_DIRS.iter().map(PathBuf::from).collect(),
+    };
+    pkg_config_path
+        .into_iter()
+        .flat_map(split_paths)
+        .chain(defaults)
+        .collect()
+}
+
+/// Search directories from `PKG_CONFIG_PATH` and `PKG_CONFIG_LIBDIR`, see [`search_dirs_from`]
+pub fn default_search_dirs() -> Vec<PathBuf> {
+    search_dirs_from(
+        std::env::var_os("PKG_CONFIG_PATH").as_deref(),
+        std::env::var_os("PKG_CONFIG_LIBDIR").as_deref(),
+    )
+}
+
+/// Options controlling where and how pc files are discovered
+#[derive(Debug, Default, Clone)]
+pub struct DiscoveryOptions {
+    /// Directories to search, the [`default_search_dirs`] are used if empty
+    pub search_dirs: Vec<PathBuf>,
+    /// Also report pc files that are symlinks or under symlinked directories, collapsing paths
+    /// that resolve to the same file
+    pub follow_symlinks: bool,
+    /// Only keep the pc files whose file stem or package name matches one of these globs, all
+    /// files are kept if empty
+    pub only: Vec<String>,
+    /// Leave out the pc files whose file stem or package name matches one of these globs, even
+    /// when they match [`only`](Self::only)
+    pub exclude: Vec<String>,
+    /// Use the pc files listed in this file instead of searching for them, see
+    /// [`read_pc_list`]
+    pub from_list: Option<PathBuf>,
+}
+
+/// The pc files under `dir`, sorted
+///
+/// The tree is walked by several threads, as the directories holding most of the pc files,
+/// like `/usr/lib`, are large trees often on slow disks.
+fn walk_pc_files(dir: &Path, follow_symlinks: bool) -> Vec<PathBuf> {
+    let pc_files = Mutex::new(Vec::new());
+    WalkBuilder::new(dir)
+        .standard_filters(false)
+        .follow_links(follow_symlinks)
+        .build_parallel()
+        .run(|| {
+            Box::new(|dir_entry| {
+                let Ok(dir_entry) = dir_entry else {
+                    return WalkState::Continue;
+                };
+                let is_file = dir_entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+                    || (follow_symlinks && dir_entry.path().is_file());
+                if is_file && dir_entry.path().extension().is_some_and(|ex| ex == "pc") {
+                    pc_files
+                        .lock()
+                        .expect("a walker thread panicked")
+                        .push(dir_entry.into_path());
+                }
+                WalkState::Continue
+            })
+        });
+    let mut pc_files = pc_files.into_inner().expect("a walker thread panicked");
+    pc_files.sort();
+    pc_files
+}
+
+/// Recursively find the pc files under each of `roots`
+///
+/// Each root is walked by several threads in parallel. The results are still in the order
+/// of the roots and the paths under each root are sorted, so the output doesn't depend on the
+/// walk's scheduling or the filesystem's traversal order. Symlinked pc files and directories are
+/// skipped unless `follow_symlinks` is set, in which case only the first path to each file is
+/// kept. Symlink loops are not descended into.
+pub fn find_pc_files(roots: &[PathBuf], follow_symlinks: bool) -> Vec<PathBuf> {
+    let roots = unique_paths(roots.iter().cloned());
+    let pc_files = roots
+        .iter()
+        .flat_map(|root| walk_pc_files(root, follow_symlinks));
+    if follow_symlinks {
+        unique_paths(pc_files)
+    } else {
+        pc_files.collect()
+    }
+}
+
+/// The first of the `paths` that resolve to the same path, the paths that can't be resolved
+/// are compared as they are
+fn unique_paths(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+impl DiscoveryOptions {
+    /// The directories searched, the configured ones or else the [`default_search_dirs`]
+    pub fn roots(&self) -> Vec<PathBuf> {
+        match self.search_dirs.as_slice() {
+            [] => default_search_dirs(),
+            search_dirs => search_dirs.to_vec(),
+        }
+    }
+}
+
+/// Regex matching the whole of a glob, where `*` matches any run of characters and `?` any
+/// single character
+fn glob_regex(glob: &str) -> Regex {
+    let pattern = regex::escape(glob).replace(r"\*", ".*").replace(r"\?", ".");
+    Regex::new(&format!("^{}$", pattern)).expect("escaped glob is a valid regex")
+}
+
+/// Whether the file stem or package name of the pc file at `path` with the contents `data`
+/// matches one of `globs`
+///
+/// The file is only parsed if its stem doesn't match.
+fn matches_any(globs: &[Regex], path: &Path, data: Option<&str>) -> bool {
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    globs.iter().any(|glob| glob.is_match(stem))
+        || data
+            .and_then(|data| PkgConfigFile::parse(data).ok())
+            .is_some_and(|pkg_config| globs.iter().any(|glob| glob.is_match(&pkg_config.name)))
+}
+
+/// The pc files listed in the file at `path`, one per line
+///
+/// Blank lines and lines starting with `#` are ignored. Relative paths are relative to the
+/// directory of the list file.
+pub fn read_pc_list(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let data = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok(data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+/// The first `@VAR@` placeholder in the `data` of a pc file that was never configured, e.g. a
+/// `.pc.in` template installed as is, ignoring comments
+fn unresolved_placeholder(data: &str) -> Option<String> {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let re = PLACEHOLDER.get_or_init(|| Regex::new(r"@[A-Za-z_][A-Za-z0-9_]*@").unwrap());
+    data.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .find_map(|line| re.find(line))
+        .map(|placeholder| placeholder.as_str().to_string())
+}
+
+/// Find the pc files in the configured search directories, or read them from the configured
+/// list file
+///
+/// Only files with the `.pc` extension are searched for, so `.pc.in` templates are never
+/// found. Files still containing a template placeholder like `@FOO@` are skipped with a
+/// warning, as they can't be converted.
+pub fn discover(options: &DiscoveryOptions) -> Result<Vec<PathBuf>, Error> {
+    let pc_files = match &options.from_list {
+        Some(list) => read_pc_list(list)?,
+        None => find_pc_files(&options.roots(), options.follow_symlinks),
+    };
+    let only: Vec<_> = options.only.iter().map(|glob| glob_regex(glob)).collect();
+    let exclude: Vec<_> = options
+        .exclude
+        .iter()
+        .map(|glob| glob_regex(glob))
+        .collect();
+    // Each file is read once for both its placeholders and its package name
+    Ok(pc_files
+        .into_iter()
+        .filter(|path| {
+            let data = std::fs::read_to_string(path).ok();
+            if let Some(placeholder) = data.as_deref().and_then(unresolved_placeholder) {
+                eprintln!(
+                    "Warning: skipping `{}`: unresolved template placeholder `{}`",
+                    path.display(),
+                    placeholder
+                );
+                return false;
+            }
+            (only.is_empty() || matches_any(&only, path, data.as_deref()))
+                && !matches_any(&exclude, path, data.as_deref())
+        })
+        .collect())
+}
+
+/// The pc file of the package `name`, the first discovered file named `<name>.pc` like
+/// pkg-config looks packages up
+pub fn find_pc_file(name: &str, options: &DiscoveryOptions) -> Result<PathBuf, Error> {
+    discover(options)?
+        .into_iter()
+        .find(|path| path.file_stem() == Some(OsStr::new(name)))
+        .ok_or_else(|| Error::PackageNotFound {
+            name: name.to_string(),
+            search_dirs: options.roots(),
+        })
+}
+
+/// Name and version of a discovered pc file, or why it couldn't be parsed
+#[skip_serializing_none]
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct PcFileSummary {
+    pub path: PathBuf,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub error: Option<String>,
+}
+
+impl PcFileSummary {
+    fn read(path: PathBuf) -> Self {
+        match std::fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|data| PkgConfigFile::parse(&data))
+        {
+            Ok(pkg_config) => Self {
+                path,
+                name: Some(pkg_config.name),
+                version: Some(pkg_config.version),
+                error: None,
+            },
+            Err(error) => Self {
+                path,
+                name: None,
+                version: None,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+/// Discover the pc files and summarize each one, files that fail to parse are included with
+/// the error
+pub fn list_packages(options: &DiscoveryOptions) -> Result<Vec<PcFileSummary>, Error> {
+    Ok(discover(options)?
+        .into_iter()
+        .map(PcFileSummary::read)
+        .collect())
+}
+
+pub fn print_package_list(options: &DiscoveryOptions, json: bool) -> Result<()> {
+    let packages = list_packages(options)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&packages)?);
+        return Ok(());
+    }
+
+    let column = |value: &Option<String>| value.clone().unwrap_or_else(|| "?".to_string());
+    let name_width = packages.iter().map(|p| column(&p.name).len()).max();
+    let version_width = packages.iter().map(|p| column(&p.version).len()).max();
+    let (name_width, version_width) = (name_width.unwrap_or(0), version_width.unwrap_or(0));
+    for package in &packages {
+        let mut line = format!(
+            "{:name_width$}  {:version_width$}  {}",
+            column(&package.name),
+            column(&package.version),
+            package.path.display(),
+        );
+        if let Some(error) = package.error.as_ref().and_then(|e| e.lines().next()) {
+            line.push_str(&format!("  (error: {})", error));
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_find_pc_files() {
+    let dir = crate::test_util::TempDir::new();
+    dir.write("lib/pkgconfig/foo.pc", "");
+    dir.write("share/pkgconfig/nested/bar.pc", "");
+    dir.write("lib/pkgconfig/README", "");
+    dir.write("lib/pkgconfig/foo.pc.in", "");
+
+    let pc_files = find_pc_files(&[dir.path().join("lib"), dir.path().join("share")], false);
+    assert_eq!(
+        pc_files,
+        vec![
+            dir.path().join("lib/pkgconfig/foo.pc"),
+            dir.path().join("share/pkgconfig/nested/bar.pc"),
+        ]
+    );
+    assert_eq!(
+        find_pc_files(&[PathBuf::from("/does/not/exist")], false),
+        Vec::<PathBuf>::new()
+    );
+}
+
+#[test]
+fn test_search_dirs_from_pkg_config_env() {
+    let dir = crate::test_util::TempDir::new();
+    let first = dir.write("first/foo.pc", "");
+    let second = dir.write("second/bar.pc", "");
+    let libdir = dir.write("libdir/baz.pc", "");
+    let path_var =
+        std::env::join_paths([dir.path().join("first"), dir.path().join("second")]).unwrap();
+    let libdir_var = dir.path().join("libdir").into_os_string();
+
+    let search_dirs = search_dirs_from(Some(&path_var), None);
+    assert_eq!(
+        search_dirs[..2],
+        [dir.path().join("first"), dir.path().join("second")]
+    );
+    assert_eq!(search_dirs.len(), 2 + DEFAULT_SEARCH_DIRS.len());
+
+    let search_dirs = search_dirs_from(Some(&path_var), Some(&libdir_var));
+    assert_eq!(
+        search_dirs,
+        vec![
+            dir.path().join("first"),
+            dir.path().join("second"),
+            dir.path().join("libdir"),
+        ]
+    );
+    assert_eq!(
+        find_pc_files(&search_dirs, false),
+        vec![first, second, libdir]
+    );
+
+    // an empty libdir disables the default search directories
+    assert_eq!(
+        search_dirs_from(None, Some(OsStr::new(""))),
+        Vec::<PathBuf>::new()
+    );
+    assert_eq!(
+        search_dirs_from(None, Some(&libdir_var)),
+        vec![dir.path().join("libdir")]
+    );
+}
+
+#[test]
+fn test_find_pc_files_is_sorted() {
+    let dir = crate::test_util::TempDir::new();
+    for name in ["zlib", "a/nested", "libpng", "b", "Xau", "m/libfoo"] {
+        dir.write(format!("{}.pc", name), "");
+    }
+
+    let pc_files = find_pc_files(&[dir.path().to_path_buf()], false);
+    assert_eq!(pc_files.len(), 6);
+    assert!(
+        pc_files.windows(2).all(|pair| pair[0] < pair[1]),
+        "{:?}",
+        pc_files
+    );
+}
+
+#[test]
+fn test_find_pc_files_in_wide_tree() {
+    let dir = crate::test_util::TempDir::new();
+    let mut expected = Vec::new();
+    for subdir in 0..16 {
+        for file in 0..16 {
+            expected.push(dir.write(format!("{:02}/nested/{:02}.pc", subdir, file), ""));
+        }
+        dir.write(format!("{:02}/README", subdir), "");
+    }
+
+    // walked by many threads, every file is found once and the order is still sorted
+    assert_eq!(find_pc_files(&[dir.path().to_path_buf()], false), expected);
+}
+
+#[test]
+fn test_unique_paths() {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write("foo.pc", "");
+    let missing = [dir.path().join("gone/a.pc"), dir.path().join("gone/b.pc")];
+    assert_eq!(
+        unique_paths([
+            foo.clone(),
+            missing[0].clone(),
+            dir.path().join("./foo.pc"),
+            missing[1].clone(),
+            missing[0].clone(),
+        ]),
+        [foo, missing[0].clone(), missing[1].clone()]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_find_symlinked_pc_files() {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write("lib/pkgconfig/foo.pc", "");
+    std::os::unix::fs::symlink(&foo, dir.path().join("lib/pkgconfig/foo-1.0.pc")).unwrap();
+    std::os::unix::fs::symlink(&foo, dir.path().join("lib/pkgconfig/bar.pc")).unwrap();
+    let roots = [dir.path().join("lib")];
+
+    assert_eq!(find_pc_files(&roots, false), vec![foo.clone()]);
+    // bar.pc sorts first, so it is the path kept for foo.pc
+    assert_eq!(
+        find_pc_files(&roots, true),
+        vec![dir.path().join("lib/pkgconfig/bar.pc")]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_find_pc_files_in_symlinked_dirs() {
+    let dir = crate::test_util::TempDir::new();
+    dir.write("opt/foo/lib/pkgconfig/foo.pc", "");
+    std::fs::create_dir_all(dir.path().join("usr")).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("opt/foo"), dir.path().join("usr/local")).unwrap();
+    // a loop back to the root doesn't make the walk run forever
+    std::os::unix::fs::symlink(dir.path().join("usr"), dir.path().join("usr/loop")).unwrap();
+    let roots = [dir.path().join("usr")];
+
+    assert!(find_pc_files(&roots, false).is_empty());
+    assert_eq!(
+        find_pc_files(&roots, true),
+        vec![dir.path().join("usr/local/lib/pkgconfig/foo.pc")]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_find_pc_files_in_default_dirs() {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write("usr/lib64/pkgconfig/foo.pc", "Name: foo\n");
+    let bar = dir.write("usr/lib/pkgconfig/bar.pc", "Name: bar\n");
+    std::os::unix::fs::symlink(dir.path().join("usr/lib"), dir.path().join("lib")).unwrap();
+    let roots: Vec<_> = DEFAULT_SEARCH_DIRS
+        .iter()
+        .map(|root| dir.path().join(root.trim_start_matches('/')))
+        .collect();
+
+    // `lib` is the same directory as `usr/lib`, so `bar.pc` is only found once
+    assert_eq!(find_pc_files(&roots, false), vec![bar, foo]);
+}
+
+#[test]
+fn test_find_pc_files_in_deep_trees() {
+    let dir = crate::test_util::TempDir::new();
+    let roots = ["c", "a", "b"].map(|root| dir.path().join(root));
+    let mut expected = Vec::new();
+    for root in &roots {
+        let mut pc_files = Vec::new();
+        let mut nested = root.strip_prefix(dir.path()).unwrap().to_path_buf();
+        for depth in 0..6 {
+            for name in ["x", "y"] {
+                let path = nested.join(format!("{}{}.pc", name, depth));
+                pc_files.push(dir.write(path.to_str().unwrap(), "Name: x\n"));
+            }
+            dir.write(nested.join("notes.txt").to_str().unwrap(), "");
+            nested.push(format!("d{}", depth));
+        }
+        // the roots are kept in the given order, each one sorted
+        pc_files.sort();
+        expected.extend(pc_files);
+    }
+
+    for _ in 0..4 {
+        assert_eq!(find_pc_files(&roots, false), expected);
+    }
+}
+
+#[test]
+fn test_discover_only() {
+    let dir = crate::test_util::TempDir::new();
+    let fcl = dir.write_pc("pc/fcl.pc", "fcl");
+    let boost = dir.write_pc("pc/boost-1.83.pc", "boost");
+    dir.write_pc("pc/foo.pc", "foo");
+    let options = |only: &[&str]| DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        only: only.iter().map(|glob| glob.to_string()).collect(),
+        ..DiscoveryOptions::default()
+    };
+
+    assert_eq!(discover(&options(&["fcl"])).unwrap(), vec![fcl.clone()]);
+    // matched by package name rather than file stem
+    assert_eq!(discover(&options(&["boost"])).unwrap(), vec![boost.clone()]);
+    assert_eq!(
+        discover(&options(&["b*", "f?l"])).unwrap(),
+        vec![boost, fcl]
+    );
+    assert_eq!(discover(&options(&[])).unwrap().len(), 3);
+    assert!(discover(&options(&["fc"])).unwrap().is_empty());
+}
+
+#[test]
+fn test_discover_exclude() {
+    let dir = crate::test_util::TempDir::new();
+    dir.write_pc("pc/nss.pc", "NSS");
+    dir.write_pc("pc/nss-softokn.pc", "nss-softokn");
+    let fcl = dir.write_pc("pc/fcl.pc", "fcl");
+    let foo = dir.write_pc("pc/foo.pc", "foo");
+    let options = |only: &[&str], exclude: &[&str]| DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        only: only.iter().map(|glob| glob.to_string()).collect(),
+        exclude: exclude.iter().map(|glob| glob.to_string()).collect(),
+        ..DiscoveryOptions::default()
+    };
+
+    assert_eq!(
+        discover(&options(&[], &["nss*"])).unwrap(),
+        vec![fcl.clone(), foo]
+    );
+    // exclude wins over only
+    assert_eq!(discover(&options(&["f*"], &["foo"])).unwrap(), vec![fcl]);
+}
+
+#[test]
+fn test_discover_skips_templates() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write(
+        "pc/foo.pc",
+        "# generated from @PROJECT_NAME@.pc.in\nName: foo\nDescription: foo\nVersion: 1.0\n",
+    );
+    dir.write(
+        "pc/bar.pc.in",
+        "Name: bar\nDescription: bar\nVersion: @PROJECT_VERSION@\n",
+    );
+    dir.write(
+        "pc/baz.pc",
+        "prefix=@CMAKE_INSTALL_PREFIX@\nName: baz\nDescription: baz\nVersion: 1.0\n",
+    );
+
+    let options = DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        ..DiscoveryOptions::default()
+    };
+    assert_eq!(discover(&options)?, vec![foo]);
+    Ok(())
+}
+
+#[test]
+fn test_find_pc_file() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write_pc("first/pkgconfig/foo.pc", "foo");
+    dir.write_pc("second/foo.pc", "other foo");
+    dir.write_pc("second/foobar.pc", "foobar");
+    let options = DiscoveryOptions {
+        search_dirs: vec![dir.path().join("first"), dir.path().join("second")],
+        ..DiscoveryOptions::default()
+    };
+
+    assert_eq!(find_pc_file("foo", &options)?, foo);
+    assert!(matches!(
+        find_pc_file("bar", &options),
+        Err(Error::PackageNotFound { name, search_dirs })
+            if name == "bar" && search_dirs == options.search_dirs
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_discover_from_list() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let foo = dir.write_pc("pc/foo.pc", "foo");
+    dir.write_pc("other/bar.pc", "bar");
+    dir.write_pc("pc/baz.pc", "baz");
+    let list = dir.write(
+        "lists/pc.txt",
+        &format!("# curated\n{}\n\n  ../other/bar.pc\n", foo.display()),
+    );
+
+    let options = DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        from_list: Some(list.clone()),
+        ..DiscoveryOptions::default()
+    };
+    assert_eq!(
+        discover(&options)?,
+        vec![foo, list.parent().unwrap().join("../other/bar.pc")]
+    );
+
+    let missing = DiscoveryOptions {
+        from_list: Some(dir.path().join("missing.txt")),
+        ..DiscoveryOptions::default()
+    };
+    assert!(matches!(discover(&missing), Err(Error::Io(_))));
+    Ok(())
+}
+
+#[test]
+fn test_list_packages() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    dir.write(
+        "lib/pkgconfig/foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\n",
+    );
+    dir.write(
+        "share/pkgconfig/bar.pc",
+        "Name: bar\nDescription: Bar library\nVersion: 2.3.1\n",
+    );
+    dir.write("share/pkgconfig/broken.pc", "Description: No name\n");
+
+    let packages = list_packages(&DiscoveryOptions {
+        search_dirs: vec![dir.path().join("lib"), dir.path().join("share")],
+        ..DiscoveryOptions::default()
+    })?;
+    let summary: Vec<_> = packages
+        .iter()
+        .map(|p| (p.name.as_deref(), p.version.as_deref()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Some("foo"), Some("1.0")),
+            (Some("bar"), Some("2.3.1")),
+            (None, None),
+        ]
+    );
+    assert!(packages[2].error.as_ref().unwrap().contains("`Name`"));
+
+    let json = serde_json::to_value(&packages)?;
+    assert_eq!(json[1]["name"], "bar");
+    assert!(json[0].get("error").is_none());
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::lib_search::dump_machine;
+
+/// Compilers asked for the target triple
+const COMPILERS: &[&str] = &["gcc", "clang"];
+
+/// Environment variables that change where pc files and libraries are found
+const ENVIRONMENT: &[&str] = &[
+    "PKG_CONFIG_PATH",
+    "PKG_CONFIG_LIBDIR",
+    "PKG_CONFIG_SYSROOT_DIR",
+    "LD_LIBRARY_PATH",
+];
+
+/// What the environment looks like to the conversion, to diagnose empty or wrong output
+#[derive(Debug)]
+pub struct Report {
+    /// Each compiler with the triple it targets, `None` if it is not available
+    pub compilers: Vec<(String, Option<String>)>,
+    /// Each discovery root with whether it exists
+    pub discovery_roots: Vec<(PathBuf, bool)>,
+    /// Each directory libraries are searched in besides the `-L` paths, with whether it exists
+    pub library_dirs: Vec<(PathBuf, bool)>,
+    pub sysroot: Option<PathBuf>,
+    pub environment: Vec<(String, Option<OsString>)>,
+}
+
+impl Report {
+    pub fn new(config: &Config) -> Self {
+        let with_existence = |paths: Vec<PathBuf>| {
+            paths
+                .into_iter()
+                .map(|path| {
+                    let exists = path.is_dir();
+                    (path, exists)
+                })
+                .collect()
+        };
+        let search = &config.generate.library_search;
+        Self {
+            compilers: COMPILERS
+                .iter()
+                .map(|compiler| (compiler.to_string(), dump_machine(compiler)))
+                .collect(),
+            discovery_roots: with_existence(config.discovery.roots()),
+            library_dirs: with_existence(search.library_dirs(&[], true)),
+            sysroot: search.sysroot.clone(),
+            environment: ENVIRONMENT
+                .iter()
+                .map(|name| (name.to_string(), std::env::var_os(name)))
+                .collect(),
+        }
+    }
+
+    /// The target triple of the first available compiler
+    pub fn triple(&self) -> Option<&str> {
+        self.compilers
+            .iter()
+            .find_map(|(_, triple)| triple.as_deref())
+    }
+
+    /// What keeps the conversion from working at all
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.triple().is_none() {
+            problems.push(format!("no compiler found, tried {}", COMPILERS.join(", ")));
+        }
+        if !self.discovery_roots.iter().any(|(_, exists)| *exists) {
+            problems.push("none of the discovery roots exist".to_string());
+        }
+        problems
+    }
+}
+
+fn existence(exists: bool) -> &'static str {
+    if exists {
+        "exists"
+    } else {
+        "missing"
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Compilers:")?;
+        for (compiler, triple) in &self.compilers {
+            match triple {
+                Some(triple) => writeln!(f, "  {}: {}", compiler, triple)?,
+                None => writeln!(f, "  {}: not found", compiler)?,
+            }
+        }
+        writeln!(f, "Target triple: {}", self.triple().unwrap_or("unknown"))?;
+        writeln!(f, "Discovery roots:")?;
+        for (root, exists) in &self.discovery_roots {
+            writeln!(f, "  {} ({})", root.display(), existence(*exists))?;
+        }
+        writeln!(f, "Library search paths:")?;
+        for (dir, exists) in &self.library_dirs {
+            writeln!(f, "  {} ({})", dir.display(), existence(*exists))?;
+        }
+        match &self.sysroot {
+            Some(sysroot) => writeln!(f, "Sysroot: {}", sysroot.display())?,
+            None => writeln!(f, "Sysroot: none")?,
+        }
+        writeln!(f, "Environment:")?;
+        for (name, value) in &self.environment {
+            match value {
+                Some(value) => writeln!(f, "  {}={}", name, value.to_string_lossy())?,
+                None => writeln!(f, "  {} is not set", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the [`Report`] of the environment, failing if the conversion can't work in it
+pub fn print_doctor(config: &Config) -> Result<()> {
+    let report = Report::new(config);
+    print!("{}", report);
+    match report.problems().as_slice() {
+        [] => Ok(()),
+        problems => anyhow::bail!(problems.join("; ")),
+    }
+}
+
+#[test]
+fn test_report_problems() {
+    let dir = crate::test_util::TempDir::new();
+    let config = Config::builder()
+        .search_dir(dir.path().join("missing"))
+        .build();
+    let mut report = Report::new(&config);
+    report.compilers = vec![("gcc".to_string(), None)];
+
+    assert_eq!(
+        report.problems(),
+        [
+            "no compiler found, tried gcc, clang",
+            "none of the discovery roots exist"
+        ]
+    );
+    assert!(report.to_string().contains("missing (missing)"));
+
+    report.compilers = vec![("gcc".to_string(), Some("x86_64-linux-gnu".to_string()))];
+    report.discovery_roots = vec![(dir.path().to_path_buf(), true)];
+    assert!(report.problems().is_empty());
+    assert!(report
+        .to_string()
+        .contains("Target triple: x86_64-linux-gnu"));
+}
@@ -0,0 +1,106 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Errors returned by the parsing, conversion, and validation functions of the library
+///
+/// The I/O and JSON errors are reference counted so the error can be cloned, e.g. to cache the
+/// result of a failed library lookup.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    /// A property a pc file must have, such as `Name`, is missing
+    #[error("missing required property `{0}`")]
+    MissingProperty(String),
+    /// The variables of a pc file can't be expanded, because some refer to each other in a cycle
+    #[error("Variables could not be expanded, cyclic: {cyclic:?}, undefined: {undefined:?}")]
+    UnresolvableVariables {
+        /// The variables whose value refers back to themselves, in name order
+        cyclic: Vec<String>,
+        /// The variables referred to but never defined, in name order
+        undefined: Vec<String>,
+    },
+    /// A pc file parsed in strict mode references a variable it never defines
+    #[error("variable `{0}` is referenced but never defined")]
+    UndefinedVariable(String),
+    /// No pc file of the package was found in the search directories
+    #[error("Package `{name}` was not found in search directories `{search_dirs:?}`")]
+    PackageNotFound {
+        name: String,
+        search_dirs: Vec<PathBuf>,
+    },
+    /// A library named by a `-l` flag was not found at any of the candidate paths
+    #[error("Could not find required library `{library}` at paths: `{paths:?}`")]
+    LibraryNotFound {
+        library: String,
+        paths: Vec<PathBuf>,
+    },
+    /// A library was found at a path that is not valid UTF-8
+    #[error("Found library `{library}` at a path that is not valid UTF-8: `{path:?}`")]
+    NonUtf8Path { library: String, path: OsString },
+    /// The `cps_version` of a package is not supported
+    #[error("Unsupported CPS version: {0}")]
+    UnsupportedCpsVersion(String),
+    /// A component of a type that requires a `location` doesn't have one
+    #[error("Component `{component}` is missing attribute `location`")]
+    MissingLocation { component: String },
+    /// An interface component has a `location` or `link_location`, which only built components have
+    #[error("Interface component `{component}` has a location")]
+    UnexpectedLocation { component: String },
+    /// A symbolic component doesn't require the component it is an alias of
+    #[error("Symbolic component `{component}` has no `requires` or `link_requires` target")]
+    DanglingSymbolic { component: String },
+    /// A configuration-specific package doesn't name its `configuration`
+    #[error("Configuration-specific package `{package}` is missing attribute `configuration`")]
+    MissingConfiguration { package: String },
+    /// The `configuration` of a package is not one of its `configurations`
+    #[error(
+        "Package `{package}` has configuration `{configuration}` not listed in `configurations`"
+    )]
+    UnknownConfiguration {
+        package: String,
+        configuration: String,
+    },
+    /// The `compat_version` of a package is newer than its `version`
+    #[error("compat_version `{compat_version}` is greater than version `{version}`")]
+    CompatVersionTooNew {
+        compat_version: String,
+        version: String,
+    },
+    /// Two merged packages define the same attribute differently
+    #[error("Cannot merge packages `{package}` and `{other}`: conflicting {attribute}")]
+    MergeConflict {
+        package: String,
+        other: String,
+        attribute: String,
+    },
+    /// A component has a type that is not part of CPS, rejected when validating strictly
+    #[error("Component `{component}` has unknown type `{type_name}`")]
+    UnknownComponentType {
+        component: String,
+        type_name: String,
+    },
+    /// A package parsed strictly has an attribute that is not part of CPS
+    #[error("unknown package attribute `{0}`")]
+    UnknownField(String),
+    /// The default component of a generated package has a type it can't have
+    #[error("Unknown default component type found: {0}")]
+    UnexpectedComponentType(String),
+    #[error(transparent)]
+    Io(Arc<std::io::Error>),
+    #[error(transparent)]
+    Json(Arc<serde_json::Error>),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(Arc::new(error))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(Arc::new(error))
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+/// Older names of language standards accepted by `-std=`, with the name of the standard
+const STANDARD_ALIASES: &[(&str, &str)] = &[
+    ("c++0x", "c++11"),
+    ("c++1y", "c++14"),
+    ("c++1z", "c++17"),
+    ("c++2a", "c++20"),
+    ("c++2b", "c++23"),
+    ("gnu++0x", "gnu++11"),
+    ("gnu++1y", "gnu++14"),
+    ("gnu++1z", "gnu++17"),
+    ("gnu++2a", "gnu++20"),
+    ("gnu++2b", "gnu++23"),
+    ("c9x", "c99"),
+    ("c1x", "c11"),
+    ("c2x", "c23"),
+    ("gnu9x", "gnu99"),
+    ("gnu1x", "gnu11"),
+    ("gnu2x", "gnu23"),
+];
+
+/// Translates compile flags into CPS `compile_features`
+///
+/// `-std=` flags are translated to the standard they select, e.g. `-std=c++17` to `c++17`.
+/// More translations can be registered for flags that mean something to a project, e.g.
+/// `-fexceptions` to `exceptions`.
+#[derive(Debug, Default, Clone)]
+pub struct FeatureMap {
+    rules: HashMap<String, String>,
+}
+
+impl FeatureMap {
+    /// Translate `flag` to `feature`, replacing any earlier rule for the flag
+    pub fn register(&mut self, flag: &str, feature: &str) {
+        self.rules.insert(flag.to_string(), feature.to_string());
+    }
+
+    /// The feature `flag` translates to, if any
+    pub fn feature(&self, flag: &str) -> Option<String> {
+        if let Some(feature) = self.rules.get(flag) {
+            return Some(feature.clone());
+        }
+        let standard = flag.strip_prefix("-std=").filter(|s| !s.is_empty())?;
+        let standard = STANDARD_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == standard)
+            .map_or(standard, |(_, standard)| standard);
+        Some(standard.to_string())
+    }
+
+    /// Split compile flags into the features they translate to and the flags that don't
+    /// translate, both in order
+    pub fn translate(&self, compile_flags: Vec<String>) -> (Vec<String>, Vec<String>) {
+        let mut features = Vec::new();
+        let mut remaining = Vec::new();
+        for flag in compile_flags {
+            match self.feature(&flag) {
+                Some(feature) => features.push(feature),
+                None => remaining.push(flag),
+            }
+        }
+        (features, remaining)
+    }
+}
+
+#[test]
+fn test_standard_features() {
+    let features = FeatureMap::default();
+    assert_eq!(features.feature("-std=c++17").as_deref(), Some("c++17"));
+    assert_eq!(features.feature("-std=gnu++1z").as_deref(), Some("gnu++17"));
+    assert_eq!(features.feature("-std=c99").as_deref(), Some("c99"));
+    assert_eq!(features.feature("-std="), None);
+    assert_eq!(features.feature("-Wall"), None);
+}
+
+#[test]
+fn test_registered_features() {
+    let mut features = FeatureMap::default();
+    features.register("-fexceptions", "exceptions");
+    features.register("-std=c++17", "cxx_std_17");
+
+    let (translated, remaining) = features.translate(vec![
+        "-fexceptions".to_string(),
+        "-Wall".to_string(),
+        "-std=c++17".to_string(),
+        "-std=c11".to_string(),
+    ]);
+    assert_eq!(translated, ["exceptions", "cxx_std_17", "c11"]);
+    assert_eq!(remaining, ["-Wall"]);
+}
@@ -0,0 +1,582 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cps::{
+    load_cps, Component, ComponentFields, Configuration, MaybeComponent, Package, Registry,
+    PREFIX_TOKEN,
+};
+
+/// Compiler and linker flags aggregated from CPS components, in the form pkg-config prints them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub cflags: Vec<String>,
+    pub libs: Vec<String>,
+}
+
+impl Flags {
+    fn add_cflag(&mut self, flag: String) {
+        if !self.cflags.contains(&flag) {
+            self.cflags.push(flag);
+        }
+    }
+
+    fn add_lib(&mut self, flag: String) {
+        if !self.libs.contains(&flag) {
+            self.libs.push(flag);
+        }
+    }
+}
+
+/// Compute the install prefix of a package from the directory containing its cps file
+///
+/// `cps_path` is of the form `@prefix@/lib/cps`, so the prefix is found by stripping the
+/// components following `@prefix@` from the end of the directory of the cps file.
+pub fn install_prefix(package: &Package, cps_file: &Path) -> Option<PathBuf> {
+    let cps_path = package.cps_path.as_ref()?;
+    let relative = Path::new(cps_path.strip_prefix(PREFIX_TOKEN)?.trim_start_matches('/'));
+    let cps_dir = cps_file.parent()?;
+    if !cps_dir.ends_with(relative) {
+        return None;
+    }
+    cps_dir
+        .ancestors()
+        .nth(relative.components().count())
+        .map(PathBuf::from)
+}
+
+/// Split a library location into `-L`/`-l` flags, falling back to the full path when the
+/// filename is not of the form `lib<name>.so` or `lib<name>.a`
+fn library_link_flags(location: &str) -> Vec<String> {
+    let path = Path::new(location);
+    let library = path
+        .file_name()
+        .and_then(|filename| filename.to_str())
+        .and_then(|filename| filename.strip_prefix("lib"))
+        .and_then(|filename| {
+            filename
+                .strip_suffix(".so")
+                .or_else(|| filename.strip_suffix(".a"))
+        });
+    match (path.parent(), library) {
+        (Some(dir), Some(library)) if !dir.as_os_str().is_empty() => {
+            vec![format!("-L{}", dir.display()), format!("-l{}", library)]
+        }
+        _ => vec![location.to_string()],
+    }
+}
+
+/// Flags of a package's requirements, resolved transitively through their cps files
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ResolvedFlags {
+    /// Names of the required packages, in the order they were loaded
+    pub packages: Vec<String>,
+    pub flags: Flags,
+}
+
+/// A package loaded while resolving requirements together with its install prefix
+struct LoadedPackage {
+    package: Package,
+    prefix: Option<PathBuf>,
+}
+
+fn resolve_prefix(package: &Package, prefix: Option<&Path>, value: &str) -> Result<String> {
+    if !value.contains(PREFIX_TOKEN) {
+        return Ok(value.to_string());
+    }
+    let prefix = prefix.ok_or(anyhow!(
+        "Unable to resolve `{}` in `{}`: the install prefix of package `{}` is unknown",
+        PREFIX_TOKEN,
+        value,
+        package.name
+    ))?;
+    Ok(value.replace(PREFIX_TOKEN, &prefix.to_string_lossy()))
+}
+
+struct FlagCollector<'a> {
+    /// Where to load required packages from, requirements on other packages are ignored if unset
+    registry: Option<&'a Registry>,
+    /// The configuration whose attributes are added to those of the components that have it
+    configuration: Option<&'a str>,
+    loaded: HashMap<String, Rc<LoadedPackage>>,
+    /// Packages currently being resolved, used to detect cyclic requirements
+    stack: Vec<String>,
+    visited: HashSet<(String, String)>,
+    resolved: ResolvedFlags,
+}
+
+impl FlagCollector<'_> {
+    /// Visit a component and its requirements, adding its flags if `record` is set
+    fn visit(
+        &mut self,
+        package: &Package,
+        prefix: Option<&Path>,
+        name: &str,
+        record: bool,
+    ) -> Result<()> {
+        if !self
+            .visited
+            .insert((package.name.clone(), name.to_string()))
+        {
+            return Ok(());
+        }
+        let component = match package.components.get(name) {
+            Some(MaybeComponent::Component(component)) => component,
+            Some(MaybeComponent::Other(_)) => return Ok(()),
+            None => bail!(
+                "Component `{}` not found in package `{}`",
+                name,
+                package.name
+            ),
+        };
+        let Some(fields) = component.fields() else {
+            return Ok(());
+        };
+        let configuration = self
+            .configuration
+            .and_then(|configuration| fields.configurations.as_ref()?.get(configuration))
+            .map(configuration_fields)
+            .unwrap_or_default();
+        if record {
+            self.add_compile_flags(package, prefix, fields)?;
+            self.add_compile_flags(package, prefix, &configuration)?;
+            if let Component::Archive(_) | Component::Dylib(_) = component {
+                // The location of the configuration replaces that of the component
+                let location = [&configuration, fields]
+                    .into_iter()
+                    .find_map(|fields| fields.link_location.as_ref().or(fields.location.as_ref()));
+                if let Some(location) = location {
+                    for flag in library_link_flags(&resolve_prefix(package, prefix, location)?) {
+                        self.resolved.flags.add_lib(flag);
+                    }
+                }
+            }
+            self.add_link_flags(package, prefix, fields)?;
+            self.add_link_flags(package, prefix, &configuration)?;
+        }
+
+        let requires = fields.requires.iter().chain(&configuration.requires);
+        for required in requires.flatten() {
+            match required.split_once(':') {
+                Some(("", local)) => self.visit(package, prefix, local, record)?,
+                Some((other, local)) if other == package.name => {
+                    self.visit(package, prefix, local, record)?
+                }
+                Some((other, component)) => self.visit_package(other, Some(component))?,
+                None => self.visit_package(required, None)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a required package and visit the requested component or its default components
+    fn visit_package(&mut self, name: &str, component: Option<&str>) -> Result<()> {
+        let Some(registry) = self.registry else {
+            return Ok(());
+        };
+        if self.stack.iter().any(|package| package == name) {
+            bail!(
+                "Cyclic requirement: {} -> {}",
+                self.stack.join(" -> "),
+                name
+            );
+        }
+        let loaded = match self.loaded.get(name) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let path = registry.path(name).ok_or(anyhow!(
+                    "Could not find required package `{}` in search paths `{:?}`",
+                    name,
+                    registry.search_paths()
+                ))?;
+                let package = load_cps(path)?;
+                let prefix = install_prefix(&package, path);
+                let loaded = Rc::new(LoadedPackage { package, prefix });
+                self.loaded.insert(name.to_string(), loaded.clone());
+                self.resolved.packages.push(name.to_string());
+                loaded
+            }
+        };
+
+        let components = match component {
+            Some(component) => vec![component.to_string()],
+            None => loaded
+                .package
+                .default_components
+                .clone()
+                .unwrap_or_default(),
+        };
+        self.stack.push(name.to_string());
+        for component in &components {
+            self.visit(&loaded.package, loaded.prefix.as_deref(), component, true)?;
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn add_compile_flags(
+        &mut self,
+        package: &Package,
+        prefix: Option<&Path>,
+        fields: &ComponentFields,
+    ) -> Result<()> {
+        let flags = &mut self.resolved.flags;
+        for include in fields.includes.iter().flat_map(|i| i.values()) {
+            flags.add_cflag(format!("-I{}", resolve_prefix(package, prefix, include)?));
+        }
+        for definition in fields.definitions.iter().flat_map(|d| d.values()) {
+            flags.add_cflag(format!("-D{}", definition));
+        }
+        for flag in fields.compile_flags.iter().flat_map(|f| f.values()) {
+            flags.add_cflag(resolve_prefix(package, prefix, flag)?);
+        }
+        Ok(())
+    }
+
+    fn add_link_flags(
+        &mut self,
+        package: &Package,
+        prefix: Option<&Path>,
+        fields: &ComponentFields,
+    ) -> Result<()> {
+        let flags = &mut self.resolved.flags;
+        for flag in fields.link_flags.iter().flatten() {
+            flags.add_lib(resolve_prefix(package, prefix, flag)?);
+        }
+        for library in fields.link_libraries.iter().flatten() {
+            flags.add_lib(format!("-l{}", library));
+        }
+        Ok(())
+    }
+}
+
+/// The attributes of a configuration as those of a component, to be added to the component's
+fn configuration_fields(configuration: &Configuration) -> ComponentFields {
+    let configuration = configuration.clone();
+    ComponentFields {
+        location: configuration.location,
+        requires: configuration.requires,
+        compile_features: configuration.compile_features,
+        compile_flags: configuration.compile_flags,
+        definitions: configuration.definitions,
+        includes: configuration.includes,
+        link_features: configuration.link_features,
+        link_flags: configuration.link_flags,
+        link_languages: configuration.link_languages,
+        link_libraries: configuration.link_libraries,
+        link_location: configuration.link_location,
+        link_requires: configuration.link_requires,
+        ..ComponentFields::default()
+    }
+}
+
+fn collect(
+    package: &Package,
+    components: &[String],
+    configuration: Option<&str>,
+    prefix: Option<&Path>,
+    registry: Option<&Registry>,
+    record_package: bool,
+) -> Result<ResolvedFlags> {
+    let components = match (components, &package.default_components) {
+        ([], Some(default_components)) => default_components.as_slice(),
+        ([], None) => bail!(
+            "No component selected and package `{}` has no `default_components`",
+            package.name
+        ),
+        (components, _) => components,
+    };
+    if let Some(configuration) = configuration {
+        let known = package
+            .configurations
+            .iter()
+            .flatten()
+            .chain(package.components.values().flat_map(|component| {
+                component
+                    .fields()
+                    .and_then(|fields| fields.configurations.as_ref())
+                    .into_iter()
+                    .flat_map(|configurations| configurations.keys())
+            }))
+            .any(|known| known == configuration);
+        if !known {
+            bail!(
+                "Configuration `{}` not found in package `{}`",
+                configuration,
+                package.name
+            );
+        }
+    }
+
+    let mut collector = FlagCollector {
+        registry,
+        configuration,
+        loaded: HashMap::new(),
+        stack: vec![package.name.clone()],
+        visited: HashSet::new(),
+        resolved: ResolvedFlags::default(),
+    };
+    for component in components {
+        collector.visit(package, prefix, component, record_package)?;
+    }
+    Ok(collector.resolved)
+}
+
+/// Aggregate the compiler and linker flags of `components` and their local requirements
+///
+/// When `components` is empty the package's `default_components` are used.
+pub fn collect_flags(
+    package: &Package,
+    components: &[String],
+    prefix: Option<&Path>,
+) -> Result<Flags> {
+    Ok(collect(package, components, None, prefix, None, true)?.flags)
+}
+
+/// Aggregate the flags of `components` followed by those of every package they transitively
+/// require, loading required packages from `registry`
+///
+/// With a `configuration` the attributes the components have for it are added to theirs, e.g.
+/// its `location` is linked instead of theirs. The configuration must be one the package has.
+pub fn resolve_flags(
+    package: &Package,
+    components: &[String],
+    configuration: Option<&str>,
+    prefix: Option<&Path>,
+    registry: &Registry,
+) -> Result<ResolvedFlags> {
+    collect(
+        package,
+        components,
+        configuration,
+        prefix,
+        Some(registry),
+        true,
+    )
+}
+
+/// Flags of the packages required by the default components of `package`, see
+/// [`Package::resolve_requires`]
+pub(crate) fn resolve_requires(
+    package: &Package,
+    search_paths: &[PathBuf],
+) -> Result<ResolvedFlags> {
+    let registry = Registry::new(search_paths.to_vec());
+    collect(package, &[], None, None, Some(&registry), false)
+}
+
+pub fn print_flags(
+    filepath: &Path,
+    components: &[String],
+    configuration: Option<&str>,
+    search_paths: &[PathBuf],
+    cflags: bool,
+    libs: bool,
+) -> Result<()> {
+    let package = load_cps(filepath)?;
+    let prefix = install_prefix(&package, filepath);
+    let registry = match search_paths {
+        [] => Registry::from_env(),
+        search_paths => Registry::new(search_paths.to_vec()),
+    };
+    let flags = resolve_flags(
+        &package,
+        components,
+        configuration,
+        prefix.as_deref(),
+        &registry,
+    )?
+    .flags;
+
+    // like pkg-config, print everything when neither kind of flag is requested
+    let (cflags, libs) = if cflags || libs {
+        (cflags, libs)
+    } else {
+        (true, true)
+    };
+    let mut output = Vec::new();
+    if cflags {
+        output.extend(flags.cflags);
+    }
+    if libs {
+        output.extend(flags.libs);
+    }
+    println!("{}", output.join(" "));
+    Ok(())
+}
+
+#[cfg(test)]
+fn sample_package() -> Package {
+    use std::str::FromStr;
+    Package::from_str(include_str!("../testdata/sample.cps")).expect("sample cps is valid")
+}
+
+#[test]
+fn test_sample_flags() -> Result<()> {
+    let package = sample_package();
+    let prefix = Path::new("/opt/sample");
+
+    let flags = collect_flags(&package, &["sample-core".to_string()], Some(prefix))?;
+    assert_eq!(flags.cflags.join(" "), "-I/opt/sample/include -DSAMPLE");
+    assert_eq!(flags.libs.join(" "), "");
+
+    let flags = collect_flags(&package, &["sample-static".to_string()], Some(prefix))?;
+    assert_eq!(
+        flags.cflags.join(" "),
+        "-DSAMPLE_STATIC -I/opt/sample/include -DSAMPLE"
+    );
+
+    assert!(collect_flags(&package, &["sample-core".to_string()], None).is_err());
+    assert!(collect_flags(&package, &["missing".to_string()], Some(prefix)).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_configuration_flags() -> Result<()> {
+    let package = sample_package();
+    let prefix = Path::new("/opt/sample");
+    let registry = Registry::new(vec![]);
+    let shared = ["sample-shared".to_string()];
+
+    let flags = resolve_flags(&package, &shared, Some("debug"), Some(prefix), &registry)?.flags;
+    assert_eq!(flags.cflags.join(" "), "-I/opt/sample/include -DSAMPLE");
+    assert_eq!(flags.libs, ["/opt/sample/lib64/libsample_d.so.1.2.0"]);
+
+    let flags = resolve_flags(
+        &package,
+        &shared,
+        Some("optimized"),
+        Some(prefix),
+        &registry,
+    )?;
+    assert_eq!(flags.flags.libs, ["/opt/sample/lib64/libsample.so.1.2.0"]);
+
+    // The configuration selects the requirements of the default component too
+    let flags = resolve_flags(&package, &[], Some("static"), Some(prefix), &registry)?;
+    assert_eq!(
+        flags.flags.cflags.join(" "),
+        "-DSAMPLE_STATIC -I/opt/sample/include -DSAMPLE"
+    );
+
+    let missing = resolve_flags(&package, &shared, Some("release"), Some(prefix), &registry);
+    assert!(missing
+        .unwrap_err()
+        .to_string()
+        .contains("Configuration `release` not found"));
+    Ok(())
+}
+
+#[test]
+fn test_library_flags() -> Result<()> {
+    use std::str::FromStr;
+    let package = Package::from_str(
+        r#"{
+    "name": "foo",
+    "cps_version": "0.11.0",
+    "cps_path": "@prefix@/lib/cps",
+    "default_components": [ "foo" ],
+    "components": {
+        "foo": {
+            "type": "dylib",
+            "location": "@prefix@/lib/libfoo.so",
+            "requires": [ ":foo-extra" ],
+            "includes": { "*": [ "@prefix@/include" ] },
+            "compile_flags": { "*": [ "-std=c++17" ] },
+            "link_flags": [ "-pthread" ]
+        },
+        "foo-extra": {
+            "type": "archive",
+            "location": "@prefix@/lib/libfoo-extra.a.1",
+            "link_libraries": [ "m" ]
+        }
+    }
+}"#,
+    )?;
+
+    let prefix = install_prefix(&package, Path::new("/opt/foo/lib/cps/foo.cps"));
+    assert_eq!(prefix, Some(PathBuf::from("/opt/foo")));
+    assert_eq!(
+        install_prefix(&package, Path::new("/opt/foo/share/foo.cps")),
+        None
+    );
+
+    let flags = collect_flags(&package, &[], prefix.as_deref())?;
+    assert_eq!(flags.cflags.join(" "), "-I/opt/foo/include -std=c++17");
+    assert_eq!(
+        flags.libs.join(" "),
+        "-L/opt/foo/lib -lfoo -pthread /opt/foo/lib/libfoo-extra.a.1 -lm"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resolve_requires() -> Result<()> {
+    use crate::test_util::TempDir;
+    use std::str::FromStr;
+
+    let dir = TempDir::new();
+    let search_paths = vec![dir.path().join("share/cps")];
+    dir.write(
+        "share/cps/bar.cps",
+        r#"{
+    "name": "bar",
+    "cps_version": "0.11.0",
+    "cps_path": "@prefix@/share/cps",
+    "default_components": [ "bar" ],
+    "components": {
+        "bar": {
+            "type": "interface",
+            "definitions": [ "BAR" ],
+            "includes": [ "@prefix@/include/bar" ],
+            "link_libraries": [ "m" ]
+        }
+    }
+}"#,
+    );
+    let foo = r#"{
+    "name": "foo",
+    "cps_version": "0.11.0",
+    "default_components": [ "foo" ],
+    "components": {
+        "foo": {
+            "type": "interface",
+            "includes": [ "/opt/foo/include" ],
+            "requires": [ "bar" ]
+        }
+    }
+}"#;
+
+    let resolved = Package::from_str(foo)?.resolve_requires(&search_paths)?;
+    assert_eq!(resolved.packages, vec!["bar".to_string()]);
+    assert_eq!(
+        resolved.flags.cflags,
+        vec![
+            format!("-I{}/include/bar", dir.path().display()),
+            "-DBAR".to_string()
+        ]
+    );
+    assert_eq!(resolved.flags.libs, vec!["-lm".to_string()]);
+
+    let registry = Registry::new(search_paths.clone());
+    let resolved = resolve_flags(&Package::from_str(foo)?, &[], None, None, &registry)?;
+    assert_eq!(resolved.flags.cflags[0], "-I/opt/foo/include");
+    assert_eq!(resolved.flags.cflags.len(), 3);
+
+    // make bar require foo
+    dir.write("share/cps/foo.cps", foo);
+    dir.write(
+        "share/cps/bar.cps",
+        r#"{
+    "name": "bar",
+    "cps_version": "0.11.0",
+    "default_components": [ "bar" ],
+    "components": { "bar": { "type": "interface", "requires": [ "foo:foo" ] } }
+}"#,
+    );
+    let error = Package::from_str(foo)?
+        .resolve_requires(&search_paths)
+        .expect_err("cyclic requirement not detected");
+    assert_eq!(error.to_string(), "Cyclic requirement: foo -> bar -> foo");
+    Ok(())
+}
@@ -1,228 +1,2928 @@
-use crate::lib_search::LibraryLocation;
-use crate::{cps, lib_search, pkg_config};
+use crate::lib_search::{CachedResolver, LibraryLocation, LibraryResolver};
+use crate::progress::Progress;
+use crate::{cps, discovery, features, lib_search, license, pkg_config, platform, Error};
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use indexmap::IndexSet;
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
-
-fn find_pc_files() -> Vec<PathBuf> {
-    [
-        "/usr/lib",
-        "/usr/share",
-        "/usr/local/lib",
-        "/usr/local/share",
-    ]
-    .iter()
-    .map(PathBuf::from)
-    .flat_map(|dir| WalkDir::new(dir).into_iter().filter_map(Result::ok))
-    .filter(|dir_entry| dir_entry.file_type().is_file())
-    .filter(|dir_entry| dir_entry.path().extension().is_some_and(|ex| ex == "pc"))
-    .map(|dir_entry| PathBuf::from(dir_entry.path()))
-    .collect()
+use std::hash::Hash;
+use std::path::{Component, Path, PathBuf};
+
+/// What to do when a cps file to be written already exists
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Fail to write the file, so hand-edited files are never lost
+    #[default]
+    Error,
+    /// Leave the existing file and report it was skipped
+    Skip,
+    /// Replace the existing file
+    Force,
+}
+
+/// Indentation of the pretty-printed cps files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    /// Indent by this many spaces per level
+    Spaces(usize),
+    /// Indent by one tab per level
+    Tab,
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Self::Spaces(2)
+    }
+}
+
+impl std::str::FromStr for Indent {
+    type Err = String;
+
+    /// Parse a number of spaces or `tab`
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s.eq_ignore_ascii_case("tab") {
+            return Ok(Self::Tab);
+        }
+        s.parse()
+            .map(Self::Spaces)
+            .map_err(|_| format!("expected a number of spaces or `tab`, found `{}`", s))
+    }
+}
+
+impl Indent {
+    fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Spaces(n) => vec![b' '; *n],
+            Self::Tab => vec![b'\t'],
+        }
+    }
+}
+
+/// Options controlling how pkg-config files are converted to CPS
+#[derive(Debug, Default, Clone)]
+pub struct GenerateOptions {
+    /// Treat the nonstandard `Requires.internal` dependencies as requirements of the package
+    pub include_requires_internal: bool,
+    /// Print the pkg-config variable expansion steps to stderr
+    pub trace: bool,
+    pub parse: pkg_config::ParseOptions,
+    /// Fill in the platform of the generated packages from the host
+    pub platform: bool,
+    /// Resolve symlinks in the include and library paths that exist
+    pub canonicalize: bool,
+    /// Name the default component after the package, see [`default_component_name`]
+    pub name_default_component_after_package: bool,
+    /// Make the shared libraries found in a plugin directory `module` components, see
+    /// [`is_plugin_location`]
+    pub plugin_modules: bool,
+    /// Replace the common spellings of licenses with their SPDX identifier, see
+    /// [`license::to_spdx`]
+    pub spdx_license: bool,
+    /// Translates compile flags into `compile_features`
+    pub features: features::FeatureMap,
+    /// Write minified JSON instead of pretty-printing it
+    pub compact: bool,
+    /// Indentation of the pretty-printed JSON
+    pub indent: Indent,
+    pub overwrite: Overwrite,
+    /// Draw a progress bar on stderr when it is a terminal
+    pub progress: bool,
+    /// Don't draw the progress bar or report skipped files
+    pub quiet: bool,
+    /// Write an [`INDEX_FILENAME`] of the generated packages into the output directory
+    pub index: bool,
+    /// Print how a single pc file is converted to stderr, see [`explain`]
+    pub explain: bool,
+    pub library_search: lib_search::SearchOptions,
+}
+
+fn parse_pkg_config(
+    pc_filepath: &Path,
+    data: &str,
+    options: &GenerateOptions,
+) -> Result<pkg_config::PkgConfigFile, Error> {
+    let mut stderr = std::io::stderr();
+    let trace = options
+        .trace
+        .then_some(&mut stderr as &mut dyn std::io::Write);
+    let (pkg_config, _) = pkg_config::PkgConfigFile::parse_with_options(
+        data,
+        &options.parse.for_file(pc_filepath),
+        trace,
+    )?;
+    Ok(pkg_config)
 }
 
 impl TryFrom<pkg_config::PkgConfigFile> for cps::Package {
-    type Error = anyhow::Error;
+    type Error = Error;
+
+    fn try_from(pkg_config: pkg_config::PkgConfigFile) -> Result<cps::Package, Error> {
+        convert(pkg_config, &GenerateOptions::default())
+    }
+}
+
+/// Key of the attribute holding the pkg-config version constraints that CPS can't express, e.g.
+/// `["< 2.0"]` on a requirement or the `["= 1.0"]` of a name the package provides
+pub const VERSION_CONSTRAINTS_KEY: &str = "x-pkgconfig-version-constraints";
+
+/// Key of the package attribute holding the pkg-config `Conflicts`, which CPS has no notion of
+///
+/// The value is the list of conflicting packages, each with a `name` and its version
+/// `constraints`, e.g. `[{ "name": "bar", "constraints": [{ "op": "<", "version": "2.0" }] }]`.
+pub const CONFLICTS_KEY: &str = "x-pkgconfig-conflicts";
+
+/// Key of the package attribute holding the pkg-config `URL`
+pub const URL_KEY: &str = "x-pkgconfig-url";
 
-    fn try_from(pkg_config: pkg_config::PkgConfigFile) -> Result<cps::Package> {
-        let library_locations = lib_search::find_locations(&pkg_config)?;
+/// Key of the package attribute holding the pkg-config `Maintainer`
+pub const MAINTAINER_KEY: &str = "x-pkgconfig-maintainer";
 
-        let location_library_name = pkg_config.link_libraries.first();
-        let default_component_name = location_library_name.unwrap_or(&pkg_config.name);
+/// Key of the package attribute holding the pkg-config properties CPS has no notion of, e.g.
+/// `{ "X-Homepage": "https://example.com/foo" }`
+pub const PROPERTIES_KEY: &str = "x-pkgconfig-properties";
+
+/// The CPS requirement for a dependency with `constraints`
+///
+/// CPS only has a minimum version, so any constraint other than a single `>=` is also kept
+/// under [`VERSION_CONSTRAINTS_KEY`].
+fn requirement(mut constraints: Vec<pkg_config::VersionConstraint>) -> cps::Requirement {
+    constraints.dedup();
+    let version = constraints
+        .iter()
+        .find(|constraint| matches!(constraint.op.as_str(), ">=" | "=" | ">"))
+        .and_then(|constraint| constraint.version.clone());
+    let mut requirement = cps::Requirement {
+        version,
+        ..cps::Requirement::default()
+    };
+    let minimum_only = match constraints.as_slice() {
+        [] => true,
+        [constraint] => constraint.op == ">=",
+        _ => false,
+    };
+    if !minimum_only {
+        requirement.extra.insert(
+            VERSION_CONSTRAINTS_KEY.to_string(),
+            constraints.iter().map(ToString::to_string).collect(),
+        );
+    }
+    requirement
+}
 
-        let package_requires_map: HashMap<_, _> = pkg_config
-            .requires
+/// Whether the compile features or remaining flags select a C++ standard, so the package must
+/// be linked as C++
+fn is_cpp(compile_features: &[String], compile_flags: &[String]) -> bool {
+    compile_features
+        .iter()
+        .any(|feature| feature.starts_with("c++") || feature.starts_with("gnu++"))
+        || compile_flags
             .iter()
-            .filter(|req| req.version.is_some())
-            .map(|req| {
-                (
-                    req.name.clone(),
-                    cps::Requirement {
-                        version: req.version.clone(),
-                        ..cps::Requirement::default()
-                    },
-                )
-            })
-            .collect();
-        let package_requires_map =
-            (!package_requires_map.is_empty()).then_some(package_requires_map);
+            .any(|flag| flag.starts_with("-std=c++") || flag.starts_with("-std=gnu++"))
+}
 
-        let local_requires: Vec<String> = library_locations
-            .keys()
-            .filter(|&name| {
-                location_library_name.is_some() && name != location_library_name.unwrap()
-            })
-            .map(|name| format!(":{}", name))
-            .collect();
-        let local_requires = (!local_requires.is_empty()).then_some(local_requires);
-        let remote_requres = (!pkg_config.requires.is_empty()).then(|| {
-            pkg_config
-                .requires
+/// Drop repeated items, keeping the first occurrence of each in order
+fn dedup<T: Hash + Eq>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    items
+        .into_iter()
+        .collect::<IndexSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Drop repeated flags, keeping each flag together with its separate argument
+fn dedup_flags(flags: Vec<String>) -> Vec<String> {
+    dedup(pkg_config::group_flags(flags))
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Clean up a path from a pkg-config file, collapsing repeated separators, resolving `.` and
+/// `..` segments lexically and dropping trailing separators
+///
+/// With `canonicalize` an existing path also has its symlinks resolved.
+fn normalize_path(path: &str, canonicalize: bool) -> String {
+    if canonicalize {
+        if let Ok(path) = fs::canonicalize(path) {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return ".".to_string();
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+/// The component of a shared library found at `location`
+///
+/// The linker uses the unversioned `libfoo.so` while the loader uses the versioned file it links
+/// to, so when the location is such a symlink it is the `link_location` and the file it resolves
+/// to is the `location`.
+fn dylib_component(location: &str, canonicalize: bool) -> cps::MaybeComponent {
+    let is_symlink = fs::symlink_metadata(location).is_ok_and(|metadata| metadata.is_symlink());
+    let real = is_symlink
+        .then(|| fs::canonicalize(location).ok())
+        .flatten();
+    match real {
+        Some(real) => cps::MaybeComponent::Component(cps::Component::Dylib(cps::ComponentFields {
+            location: Some(normalize_path(&real.to_string_lossy(), canonicalize)),
+            link_location: Some(normalize_path(location, false)),
+            ..cps::ComponentFields::default()
+        })),
+        None => cps::MaybeComponent::from_dylib_location(&normalize_path(location, canonicalize)),
+    }
+}
+
+/// Directories holding runtime-loaded plugins rather than libraries to link
+const PLUGIN_DIRS: &[&str] = &["modules", "plugins"];
+
+/// Whether the shared library at `location` is a plugin, which is loaded at runtime instead of
+/// linked, because it is under a directory named `modules` or `plugins`, e.g.
+/// `/usr/lib/gio/modules/libgiognutls.so`
+pub fn is_plugin_location(location: &str) -> bool {
+    Path::new(location).parent().is_some_and(|dir| {
+        dir.iter()
+            .any(|name| PLUGIN_DIRS.iter().any(|&d| name == d))
+    })
+}
+
+/// The name of the default component of a package converted from `pkg_config`
+///
+/// It is the name of the first library of `Libs`, e.g. `fcl` for `-lfcl`, or else the package
+/// name. The conversion names a library found on disk by its SONAME instead, see
+/// [`lib_search::soname_library_name`]. With `after_package` it is always the package name,
+/// with the characters other than ASCII letters, digits, `-`, `_`, `.` and `+` replaced by `_`,
+/// e.g. `Foo_Bar` for a package named `Foo Bar`, unless another library already has that name,
+/// which then keeps its component.
+pub fn default_component_name(
+    pkg_config: &pkg_config::PkgConfigFile,
+    after_package: bool,
+) -> String {
+    let library = pkg_config
+        .link_libraries
+        .first()
+        .map(|name| lib_search::library_name(name));
+    let package: String = pkg_config
+        .name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '+' => c,
+            _ => '_',
+        })
+        .collect();
+    let taken = pkg_config
+        .link_libraries
+        .iter()
+        .chain(&pkg_config.link_libraries_private)
+        .map(|name| lib_search::library_name(name))
+        .any(|name| name == package && Some(name) != library);
+    match library {
+        Some(library) if !after_package || taken => library.to_string(),
+        _ => package,
+    }
+}
+
+/// CPS definitions are written as `NAME` or `NAME=value` for any language
+fn cps_definitions(definitions: &[pkg_config::Definition]) -> cps::LanguageStringList {
+    cps::LanguageStringList::any_language_map(
+        dedup(definitions)
+            .into_iter()
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// Where a value of a converted package came from, see [`convert_with_provenance`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The component holding the value
+    pub component: String,
+    /// The CPS attribute holding the value, e.g. `includes`
+    pub attribute: &'static str,
+    pub value: String,
+    /// The flag of the pc file the value was converted from
+    pub source: pkg_config::FlagSource,
+}
+
+/// Convert like [`convert`], also returning where the values of the components came from
+///
+/// The flags are those recorded when parsing with [`pkg_config::ParseOptions::record_sources`],
+/// without them there is no provenance. The values no flag accounts for, like requires, are left
+/// out.
+pub fn convert_with_provenance(
+    pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+) -> Result<(cps::Package, Vec<Provenance>), Error> {
+    convert_recording(pkg_config, options, &options.library_search)
+}
+
+/// Records where the values of a package being converted came from, see
+/// [`convert_with_provenance`]
+struct ProvenanceRecorder {
+    sources: Vec<pkg_config::FlagSource>,
+    provenance: Vec<Provenance>,
+}
+
+impl ProvenanceRecorder {
+    /// Record that `value` of the `attribute` of `component` came from the first flag of
+    /// `properties` that `from` accepts, trying the properties in order
+    fn record(
+        &mut self,
+        component: &str,
+        attribute: &'static str,
+        value: &str,
+        properties: &[&str],
+        from: impl Fn(&pkg_config::FlagSource) -> bool,
+    ) {
+        let recorded = self.provenance.iter().any(|provenance| {
+            provenance.component == component
+                && provenance.attribute == attribute
+                && provenance.value == value
+        });
+        let source = properties.iter().find_map(|property| {
+            self.sources
                 .iter()
-                .map(|d| d.name.clone())
-                .collect::<Vec<_>>()
+                .find(|source| source.property == *property && from(source))
         });
-        let default_component_requires = match (local_requires, remote_requres) {
-            (Some(local), Some(remote)) => Some(local.into_iter().chain(remote).collect()),
-            (Some(local), None) => Some(local),
-            (None, Some(remote)) => Some(remote),
-            (None, None) => None,
-        };
+        if let (false, Some(source)) = (recorded, source) {
+            self.provenance.push(Provenance {
+                component: component.to_string(),
+                attribute,
+                value: value.to_string(),
+                source: source.clone(),
+            });
+        }
+    }
 
-        let mut package_configurations: Option<Vec<String>> = None;
-        let mut components = HashMap::<String, cps::MaybeComponent>::new();
-        for (name, location) in library_locations {
-            match location {
-                LibraryLocation::Dylib(location) => {
-                    components.insert(
-                        name.clone(),
-                        cps::MaybeComponent::from_dylib_location(&location),
-                    );
+    /// Record the sources of the values `component` gets from the compile flags of
+    /// `cflags`, falling back to `libs` for the flags shared with linking
+    fn record_compile(
+        &mut self,
+        component: &str,
+        [cflags, libs]: [&str; 2],
+        compile_flags: &[String],
+        definitions: &[pkg_config::Definition],
+        includes: &[String],
+        options: &GenerateOptions,
+    ) {
+        for group in pkg_config::group_flags(compile_flags.to_vec()) {
+            let flag = group.join(" ");
+            let from = |source: &pkg_config::FlagSource| source.flag == flag;
+            match options.features.feature(&flag) {
+                Some(feature) => self.record(
+                    component,
+                    "compile_features",
+                    &feature,
+                    &[cflags, libs],
+                    from,
+                ),
+                None => {
+                    for value in &group {
+                        self.record(component, "compile_flags", value, &[cflags, libs], from);
+                    }
                 }
-                LibraryLocation::Archive(location) => {
-                    components.insert(
-                        name.clone(),
-                        cps::MaybeComponent::from_archive_location(&location),
-                    );
+            }
+        }
+        for definition in definitions {
+            let definition = definition.to_string();
+            self.record(component, "definitions", &definition, &[cflags], |source| {
+                source.flag.starts_with("-D") && source.value == definition
+            });
+        }
+        for include in includes {
+            let value = normalize_path(include, options.canonicalize);
+            self.record(component, "includes", &value, &[cflags], |source| {
+                (source.flag.starts_with("-I") || source.flag.starts_with("-isystem"))
+                    && source.value == *include
+            });
+        }
+    }
+
+    /// The recorded provenance in component name order
+    fn finish(mut self) -> Vec<Provenance> {
+        self.provenance
+            .sort_by(|a, b| a.component.cmp(&b.component));
+        self.provenance
+    }
+}
+
+/// Describe how `pkg_config` converts to CPS: the type of each component and why, where its
+/// libraries were found, which flags became compile features and which requires were kept
+pub fn explain(
+    pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+) -> Result<String, Error> {
+    Ok(convert_explained(pkg_config, options, &options.library_search)?.1)
+}
+
+/// Convert `pkg_config` finding its libraries with `resolver`, also returning the description
+/// of the conversion of [`explain`]
+fn convert_explained(
+    pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+    resolver: &dyn LibraryResolver,
+) -> Result<(cps::Package, String), Error> {
+    use std::fmt::Write;
+
+    let names = |dependencies: &[pkg_config::Dependency]| -> Vec<String> {
+        dependencies.iter().map(|dep| dep.name.clone()).collect()
+    };
+    let requires = [
+        ("Requires", names(&pkg_config.requires)),
+        ("Requires.private", names(&pkg_config.requires_private)),
+        ("Requires.internal", names(&pkg_config.requires_internal)),
+    ];
+
+    let (package, provenance) = convert_recording(pkg_config, options, resolver)?;
+    let source_of = |component: &str, attribute: &str| {
+        provenance.iter().find(|provenance| {
+            provenance.component == component && provenance.attribute == attribute
+        })
+    };
+
+    // Writing to a String can't fail
+    let mut explanation = String::new();
+    match &package.version {
+        Some(version) => writeln!(explanation, "Package `{}` {}", package.name, version),
+        None => writeln!(explanation, "Package `{}`", package.name),
+    }
+    .unwrap();
+
+    let mut names: Vec<_> = package.components.keys().collect();
+    names.sort();
+    for name in names {
+        let component = &package.components[name];
+        let why = match component.fields() {
+            Some(fields) if fields.location.is_some() => {
+                let location = fields.location.as_deref().unwrap_or_default();
+                let library = match component.type_name() {
+                    "archive" => "static library",
+                    "module" => "plugin",
+                    _ => "shared library",
+                };
+                match source_of(name, "location") {
+                    Some(provenance) => format!(
+                        "`{}` of `{}` resolved to the {} `{}`",
+                        provenance.source.flag, provenance.source.property, library, location
+                    ),
+                    None => format!("its {} is `{}`", library, location),
                 }
-                LibraryLocation::Both { archive, dylib } => {
-                    package_configurations = Some(vec!["shared".to_string(), "static".to_string()]);
-                    components.insert(
-                        name.clone(),
-                        cps::MaybeComponent::Component(cps::Component::Interface(
-                            cps::ComponentFields {
-                                configurations: Some(
-                                    [
-                                        (
-                                            "shared".to_string(),
-                                            cps::Configuration {
-                                                requires: Some(vec![format!(":{}-shared", name)]),
-                                                ..cps::Configuration::default()
-                                            },
-                                        ),
-                                        (
-                                            "static".to_string(),
-                                            cps::Configuration {
-                                                requires: Some(vec![format!(":{}-static", name)]),
-                                                ..cps::Configuration::default()
-                                            },
-                                        ),
-                                    ]
-                                    .into_iter()
-                                    .collect(),
-                                ),
-                                ..cps::ComponentFields::default()
-                            },
-                        )),
-                    );
-                    components.insert(
-                        format!("{}-shared", name),
-                        cps::MaybeComponent::from_dylib_location(&archive),
-                    );
-                    components.insert(
-                        format!("{}-static", name),
-                        cps::MaybeComponent::from_archive_location(&dylib),
-                    );
+            }
+            Some(fields) if fields.configurations.is_some() => {
+                "both a shared and a static library were found, its configurations select one"
+                    .to_string()
+            }
+            _ => "it has no library of its own".to_string(),
+        };
+        writeln!(
+            explanation,
+            "Component `{}` is {} {}: {}",
+            name,
+            if component.type_name() == "archive" || component.type_name() == "interface" {
+                "an"
+            } else {
+                "a"
+            },
+            component.type_name(),
+            why
+        )
+        .unwrap();
+    }
+
+    for provenance in &provenance {
+        match provenance.attribute {
+            "compile_features" => writeln!(
+                explanation,
+                "Flag `{}` of `{}` became the compile feature `{}`",
+                provenance.source.flag, provenance.source.property, provenance.value
+            ),
+            "link_libraries" => writeln!(
+                explanation,
+                "Library `{}` of `{}` was not found and is left to the linker",
+                provenance.source.flag, provenance.source.property
+            ),
+            _ => Ok(()),
+        }
+        .unwrap();
+    }
+
+    for (property, dependencies) in &requires {
+        for name in dependencies {
+            let outcome = match *property {
+                "Requires" => "kept as a requirement",
+                "Requires.private" if requires[0].1.contains(name) => {
+                    "kept as a requirement, it is also public"
                 }
+                "Requires.private" => "kept as a link requirement, for static linking",
+                _ if options.include_requires_internal => "kept as a requirement",
+                _ => "dropped, --include-requires-internal keeps it",
             };
+            writeln!(
+                explanation,
+                "Dependency `{}` of `{}` is {}",
+                name, property, outcome
+            )
+            .unwrap();
         }
+    }
+    Ok((package, explanation))
+}
 
-        let default_component = components.entry(default_component_name.clone()).or_insert(
-            cps::MaybeComponent::Component(cps::Component::Interface(
-                cps::ComponentFields::default(),
-            )),
+/// Convert a parsed pkg-config file into a CPS package
+pub fn convert(
+    pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+) -> Result<cps::Package, Error> {
+    convert_with(pkg_config, options, &options.library_search)
+}
+
+/// Convert a parsed pkg-config file, finding its libraries with `resolver`
+pub fn convert_with(
+    pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+    resolver: &dyn LibraryResolver,
+) -> Result<cps::Package, Error> {
+    Ok(convert_recording(pkg_config, options, resolver)?.0)
+}
+
+/// Convert like [`convert_with`], recording the flag each value was converted from as it is
+/// converted
+fn convert_recording(
+    mut pkg_config: pkg_config::PkgConfigFile,
+    options: &GenerateOptions,
+    resolver: &dyn LibraryResolver,
+) -> Result<(cps::Package, Vec<Provenance>), Error> {
+    let mut recorder = ProvenanceRecorder {
+        sources: std::mem::take(&mut pkg_config.sources),
+        provenance: Vec::new(),
+    };
+    // A package without libraries, e.g. a header-only one, is only an interface and has
+    // nothing to find, its private libraries are left for the linker
+    let (
+        lib_search::FoundLibraries {
+            locations: library_locations,
+            unresolved,
+        },
+        private_locations,
+    ) = if pkg_config.link_libraries.is_empty() {
+        (lib_search::FoundLibraries::default(), HashMap::new())
+    } else {
+        (
+            lib_search::find_locations(&pkg_config, resolver)?,
+            lib_search::find_private_locations(&pkg_config, resolver),
+        )
+    };
+
+    let location_library_name = pkg_config
+        .link_libraries
+        .first()
+        .map(|name| lib_search::library_name(name));
+    let default_component_name =
+        default_component_name(&pkg_config, options.name_default_component_after_package);
+    let linked_libraries: Vec<_> = pkg_config
+        .link_libraries
+        .iter()
+        .chain(&pkg_config.link_libraries_private)
+        .map(|name| lib_search::library_name(name))
+        .collect();
+    // A shared library is named by its SONAME rather than the name it is linked by, e.g. `foo-2`
+    // for `-lfoo` linking to `libfoo-2.so.1`, unless that name is already taken
+    let mut taken: HashSet<_> = linked_libraries
+        .iter()
+        .map(|name| name.to_string())
+        .chain([default_component_name.clone()])
+        .collect();
+    let mut soname_names = HashMap::new();
+    for &library in &linked_libraries {
+        let Some(LibraryLocation::Dylib(dylib) | LibraryLocation::Both { dylib, .. }) =
+            library_locations
+                .get(library)
+                .or_else(|| private_locations.get(library))
+        else {
+            continue;
+        };
+        if let Some(name) =
+            lib_search::soname_library_name(dylib).filter(|name| taken.insert(name.clone()))
+        {
+            soname_names.insert(library.to_string(), name);
+        }
+    }
+    let default_component_name = match location_library_name {
+        Some(location) if default_component_name == location => soname_names
+            .get(location)
+            .cloned()
+            .unwrap_or(default_component_name),
+        _ => default_component_name,
+    };
+    // The component of the first library is the default one
+    let component_name = |library: &str| match location_library_name {
+        Some(location) if location == library => default_component_name.clone(),
+        _ => soname_names
+            .get(library)
+            .cloned()
+            .unwrap_or_else(|| library.to_string()),
+    };
+
+    // Private libraries found on disk become components needed only for linking, the others are
+    // left for the linker to find like the libraries of `Libs` that weren't found
+    let (link_requires, link_libraries): (Vec<_>, Vec<_>) = pkg_config
+        .link_libraries_private
+        .iter()
+        .filter(|&name| !pkg_config.link_libraries.contains(name))
+        .partition(|&name| private_locations.contains_key(lib_search::library_name(name)));
+    let mut link_requires = dedup(
+        link_requires
+            .into_iter()
+            .map(|name| format!(":{}", component_name(lib_search::library_name(name)))),
+    );
+    let link_libraries = dedup(
+        unresolved
+            .into_iter()
+            .chain(link_libraries.into_iter().cloned()),
+    );
+
+    // The soname tells the oldest compatible version when it follows the package versioning,
+    // e.g. `libfoo.so.2` of foo 2.3.1 is compatible back to foo 2
+    let compat_version = location_library_name
+        .and_then(|name| match library_locations.get(name)? {
+            LibraryLocation::Dylib(dylib) | LibraryLocation::Both { dylib, .. } => {
+                lib_search::abi_major_version(dylib)
+            }
+            LibraryLocation::Archive(_) => None,
+        })
+        .filter(|major| pkg_config.version.split('.').next() == Some(major.as_str()));
+
+    let requires: Vec<_> = pkg_config
+        .requires
+        .iter()
+        .chain(
+            options
+                .include_requires_internal
+                .then_some(&pkg_config.requires_internal)
+                .into_iter()
+                .flatten(),
+        )
+        .collect();
+
+    // The package requires map holds every external dependency and is what the component
+    // requires and link requires refer to
+    let mut package_constraints = HashMap::<String, Vec<pkg_config::VersionConstraint>>::new();
+    let mut remote_requires = Vec::new();
+    for req in &requires {
+        package_constraints
+            .entry(req.name.clone())
+            .or_insert_with(|| {
+                remote_requires.push(req.name.clone());
+                Vec::new()
+            })
+            .extend(req.constraints.iter().cloned());
+    }
+    // Private requirements are only needed to link statically, so they are link requirements of
+    // the default component unless they are also public
+    for req in &pkg_config.requires_private {
+        package_constraints
+            .entry(req.name.clone())
+            .or_insert_with(|| {
+                link_requires.push(req.name.clone());
+                Vec::new()
+            })
+            .extend(req.constraints.iter().cloned());
+    }
+    let package_requires_map: HashMap<_, _> = package_constraints
+        .into_iter()
+        .map(|(name, constraints)| (name, requirement(constraints)))
+        .collect();
+    let package_requires_map = (!package_requires_map.is_empty()).then_some(package_requires_map);
+
+    let local_requires: Vec<String> = library_locations
+        .keys()
+        .filter(|&name| location_library_name.is_some_and(|location| name != location))
+        .map(|name| format!(":{}", component_name(name)))
+        .collect();
+    let local_requires = (!local_requires.is_empty()).then_some(local_requires);
+    let remote_requres = (!remote_requires.is_empty()).then_some(remote_requires);
+    let default_component_requires = match (local_requires, remote_requres) {
+        (Some(local), Some(remote)) => Some(local.into_iter().chain(remote).collect()),
+        (Some(local), None) => Some(local),
+        (None, Some(remote)) => Some(remote),
+        (None, None) => None,
+    };
+
+    let path = |path: &str| normalize_path(path, options.canonicalize);
+    let mut package_configurations: Option<Vec<String>> = None;
+    let mut components = HashMap::<String, cps::MaybeComponent>::new();
+    // The components of each library with the property and name of the library
+    let mut library_components = Vec::new();
+    for (property, library, location) in library_locations
+        .into_iter()
+        .map(|(library, location)| ("Libs", library, location))
+        .chain(
+            private_locations
+                .into_iter()
+                .map(|(library, location)| ("Libs.private", library, location)),
+        )
+    {
+        let name = component_name(&library);
+        let names = match &location {
+            LibraryLocation::Both { .. } => {
+                vec![format!("{}-shared", name), format!("{}-static", name)]
+            }
+            _ => vec![name.clone()],
+        };
+        library_components.extend(
+            names
+                .into_iter()
+                .map(|name| (name, property, library.clone())),
         );
-        let default_component = match default_component {
-            cps::MaybeComponent::Component(cps::Component::Interface(fields)) => fields,
-            cps::MaybeComponent::Component(cps::Component::Dylib(fields)) => fields,
-            cps::MaybeComponent::Component(cps::Component::Archive(fields)) => fields,
-            component => {
-                anyhow::bail!("Unknwon default component type found: {:?}", component)
+        match location {
+            LibraryLocation::Dylib(location)
+                if options.plugin_modules && is_plugin_location(&location) =>
+            {
+                components.insert(
+                    name.clone(),
+                    cps::MaybeComponent::Component(cps::Component::Module(cps::ComponentFields {
+                        location: Some(path(&location)),
+                        ..cps::ComponentFields::default()
+                    })),
+                );
+            }
+            LibraryLocation::Dylib(location) => {
+                components.insert(
+                    name.clone(),
+                    dylib_component(&location, options.canonicalize),
+                );
+            }
+            LibraryLocation::Archive(location) => {
+                components.insert(
+                    name.clone(),
+                    cps::MaybeComponent::from_archive_location(&path(&location)),
+                );
+            }
+            LibraryLocation::Both { archive, dylib } => {
+                package_configurations = Some(vec!["shared".to_string(), "static".to_string()]);
+                components.insert(
+                    name.clone(),
+                    cps::MaybeComponent::Component(cps::Component::Interface(
+                        cps::ComponentFields {
+                            configurations: Some(
+                                [
+                                    (
+                                        "shared".to_string(),
+                                        cps::Configuration {
+                                            requires: Some(vec![format!(":{}-shared", name)]),
+                                            ..cps::Configuration::default()
+                                        },
+                                    ),
+                                    (
+                                        "static".to_string(),
+                                        cps::Configuration {
+                                            requires: Some(vec![format!(":{}-static", name)]),
+                                            ..cps::Configuration::default()
+                                        },
+                                    ),
+                                ]
+                                .into_iter()
+                                .collect(),
+                            ),
+                            ..cps::ComponentFields::default()
+                        },
+                    )),
+                );
+                components.insert(
+                    format!("{}-shared", name),
+                    dylib_component(&dylib, options.canonicalize),
+                );
+                components.insert(
+                    format!("{}-static", name),
+                    cps::MaybeComponent::from_archive_location(&path(&archive)),
+                );
+            }
+        };
+    }
+
+    // `-l:libfoo.so.1` names the exact file to link
+    for library in pkg_config
+        .link_libraries
+        .iter()
+        .chain(&pkg_config.link_libraries_private)
+        .filter(|library| library.starts_with(':'))
+    {
+        if let Some(cps::MaybeComponent::Component(
+            cps::Component::Dylib(fields) | cps::Component::Archive(fields),
+        )) = components.get_mut(&component_name(lib_search::library_name(library)))
+        {
+            if fields.link_location.is_none() {
+                fields.link_location = fields.location.clone();
+            }
+        }
+    }
+
+    for (name, property, library) in &library_components {
+        let Some(fields) = components
+            .get(name)
+            .and_then(|component| component.fields())
+        else {
+            continue;
+        };
+        for (attribute, value) in [
+            ("location", &fields.location),
+            ("link_location", &fields.link_location),
+        ] {
+            if let Some(value) = value {
+                recorder.record(name, attribute, value, &[property], |source| {
+                    source.flag.starts_with("-l")
+                        && lib_search::library_name(&source.value) == library
+                });
+            }
+        }
+    }
+
+    let default_component =
+        components
+            .entry(default_component_name.clone())
+            .or_insert(cps::MaybeComponent::Component(cps::Component::Interface(
+                cps::ComponentFields::default(),
+            )));
+    let default_component = match default_component {
+        cps::MaybeComponent::Component(cps::Component::Interface(fields)) => fields,
+        cps::MaybeComponent::Component(cps::Component::Dylib(fields)) => fields,
+        cps::MaybeComponent::Component(cps::Component::Archive(fields)) => fields,
+        cps::MaybeComponent::Component(cps::Component::Module(fields)) => fields,
+        component => return Err(Error::UnexpectedComponentType(format!("{:?}", component))),
+    };
+
+    // Requires could be per-configuration or on the component
+    if default_component_requires.is_some() {
+        if let Some(configurations) = &mut default_component.configurations {
+            for configuration in configurations.values_mut() {
+                configuration.requires = Some(
+                    [
+                        &configuration.requires.clone().unwrap_or_default()[..],
+                        &default_component_requires.clone().unwrap_or_default()[..],
+                    ]
+                    .concat(),
+                );
             }
+        } else {
+            default_component.requires = default_component_requires;
+        }
+    }
+
+    recorder.record_compile(
+        &default_component_name,
+        ["Cflags", "Libs"],
+        &pkg_config.compile_flags,
+        &pkg_config.definitions,
+        &pkg_config.includes,
+        options,
+    );
+    recorder.record_compile(
+        &format!("{}-private", default_component_name),
+        ["Cflags.private", "Libs.private"],
+        &pkg_config.compile_flags_private,
+        &pkg_config.definitions_private,
+        &pkg_config.includes_private,
+        options,
+    );
+    let (compile_features, compile_flags) = options
+        .features
+        .translate(dedup_flags(pkg_config.compile_flags));
+    let (compile_features_private, compile_flags_private) = options
+        .features
+        .translate(dedup_flags(pkg_config.compile_flags_private));
+    let compile_features = dedup(compile_features);
+    let compile_features_private = dedup(compile_features_private);
+    default_component.link_languages =
+        is_cpp(&compile_features, &compile_flags).then(|| vec!["c++".to_string()]);
+    default_component.compile_features = (!compile_features.is_empty()).then_some(compile_features);
+    default_component.compile_flags = (!compile_flags.is_empty())
+        .then(|| cps::LanguageStringList::any_language_map(compile_flags));
+    default_component.definitions =
+        (!pkg_config.definitions.is_empty()).then(|| cps_definitions(&pkg_config.definitions));
+    default_component.includes = (!pkg_config.includes.is_empty()).then(|| {
+        cps::LanguageStringList::any_language_map(dedup(
+            pkg_config.includes.iter().map(|include| path(include)),
+        ))
+    });
+    // The link flags keep the order of `Libs`, only repeated flags are dropped. CPS has no
+    // notion of frameworks, so they are linked by their `-framework` flag where they appear.
+    let link_flags = dedup_flags(pkg_config.link_flags);
+    for group in pkg_config::group_flags(link_flags.clone()) {
+        let flag = group.join(" ");
+        for value in &group {
+            recorder.record(
+                &default_component_name,
+                "link_flags",
+                value,
+                &["Libs", "Cflags"],
+                |source| source.flag == flag,
+            );
+        }
+    }
+    for library in &link_libraries {
+        recorder.record(
+            &default_component_name,
+            "link_libraries",
+            library,
+            &["Libs", "Libs.private"],
+            |source| source.flag.starts_with("-l") && source.value == *library,
+        );
+    }
+    default_component.link_flags = (!link_flags.is_empty()).then_some(link_flags);
+    default_component.link_requires = (!link_requires.is_empty()).then_some(link_requires);
+    default_component.link_libraries = (!link_libraries.is_empty()).then_some(link_libraries);
+
+    // The private compile flags are kept apart in a component requiring the default one, for
+    // the consumers linking statically to opt into
+    if !(pkg_config.includes_private.is_empty()
+        && pkg_config.definitions_private.is_empty()
+        && compile_flags_private.is_empty()
+        && compile_features_private.is_empty())
+    {
+        components.insert(
+            format!("{}-private", default_component_name),
+            cps::MaybeComponent::Component(cps::Component::Interface(cps::ComponentFields {
+                requires: Some(vec![format!(":{}", default_component_name)]),
+                compile_features: (!compile_features_private.is_empty())
+                    .then_some(compile_features_private),
+                compile_flags: (!compile_flags_private.is_empty())
+                    .then(|| cps::LanguageStringList::any_language_map(compile_flags_private)),
+                definitions: (!pkg_config.definitions_private.is_empty())
+                    .then(|| cps_definitions(&pkg_config.definitions_private)),
+                includes: (!pkg_config.includes_private.is_empty()).then(|| {
+                    cps::LanguageStringList::any_language_map(dedup(
+                        pkg_config
+                            .includes_private
+                            .iter()
+                            .map(|include| path(include)),
+                    ))
+                }),
+                ..cps::ComponentFields::default()
+            })),
+        );
+    }
+
+    // Each provided name is an alias of the default component
+    for provide in &pkg_config.provides {
+        if provide.name == pkg_config.name || components.contains_key(&provide.name) {
+            continue;
+        }
+        let mut fields = cps::ComponentFields {
+            requires: Some(vec![format!(":{}", default_component_name)]),
+            ..cps::ComponentFields::default()
         };
+        if !provide.constraints.is_empty() {
+            fields.extra.insert(
+                VERSION_CONSTRAINTS_KEY.to_string(),
+                provide
+                    .constraints
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            );
+        }
+        components.insert(
+            provide.name.clone(),
+            cps::MaybeComponent::Component(cps::Component::Symbolic(fields)),
+        );
+    }
 
-        // Requires could be per-configuration or on the component
-        if default_component_requires.is_some() {
-            if let Some(configurations) = &mut default_component.configurations {
-                for configuration in configurations.values_mut() {
-                    configuration.requires = Some(
-                        [
-                            &configuration.requires.clone().unwrap_or_default()[..],
-                            &default_component_requires.clone().unwrap_or_default()[..],
-                        ]
-                        .concat(),
+    let mut cps = cps::Package {
+        name: pkg_config.name.clone(),
+        version: (!pkg_config.version.is_empty()).then_some(pkg_config.version),
+        compat_version,
+        description: Some(pkg_config.description),
+        default_components: Some(vec![default_component_name.clone()]),
+        requires: package_requires_map,
+        components,
+        configurations: package_configurations,
+        ..cps::Package::default()
+    };
+    if options.platform {
+        cps.platform = Some(platform::detect());
+    }
+    cps.license = pkg_config.license.map(|license| {
+        if !options.spdx_license {
+            return license;
+        }
+        match license::to_spdx(&license) {
+            Some(identifier) => identifier.to_string(),
+            None => {
+                if !options.quiet {
+                    eprintln!(
+                        "Warning: license `{}` of `{}` has no known SPDX identifier, keeping it as is",
+                        license, cps.name
                     );
                 }
-            } else {
-                default_component.requires = default_component_requires;
-            }
-        }
-
-        default_component.compile_flags = (!pkg_config.compile_flags.is_empty())
-            .then(|| cps::LanguageStringList::any_language_map(pkg_config.compile_flags));
-        default_component.definitions = (!pkg_config.definitions.is_empty())
-            .then(|| cps::LanguageStringList::any_language_map(pkg_config.definitions));
-        default_component.includes = (!pkg_config.includes.is_empty())
-            .then(|| cps::LanguageStringList::any_language_map(pkg_config.includes));
-        default_component.link_flags =
-            (!pkg_config.link_flags.is_empty()).then_some(pkg_config.link_flags);
-
-        let cps = cps::Package {
-            name: pkg_config.name.clone(),
-            version: Some(pkg_config.version),
-            description: Some(pkg_config.description),
-            default_components: Some(vec![default_component_name.clone()]),
-            requires: package_requires_map,
-            components,
-            configurations: package_configurations,
-            ..cps::Package::default()
-        };
-        Ok(cps)
+                license
+            }
+        }
+    });
+    for (key, value) in [
+        (URL_KEY, pkg_config.url),
+        (MAINTAINER_KEY, pkg_config.maintainer),
+    ] {
+        if let Some(value) = value {
+            cps.extra.insert(key.to_string(), value.into());
+        }
+    }
+    if !pkg_config.extra.is_empty() {
+        cps.extra.insert(
+            PROPERTIES_KEY.to_string(),
+            serde_json::to_value(&pkg_config.extra)?,
+        );
+    }
+    if !pkg_config.conflicts.is_empty() {
+        cps.extra.insert(
+            CONFLICTS_KEY.to_string(),
+            serde_json::to_value(&pkg_config.conflicts)?,
+        );
     }
+    Ok((cps, recorder.finish()))
 }
 
-pub fn generate_all_from_pkg_config(outdir: &Path) -> Result<()> {
-    let pc_files = find_pc_files();
+/// Serialize `value` as the options ask, pretty-printed with their indentation unless they ask
+/// for compact JSON
+///
+/// The JSON ends with a newline either way, as POSIX text files do.
+fn to_json<T: serde::Serialize>(
+    value: &T,
+    options: &GenerateOptions,
+) -> serde_json::Result<String> {
+    let mut json = Vec::new();
+    if options.compact {
+        serde_json::to_writer(&mut json, value)?;
+    } else {
+        let indent = options.indent.as_bytes();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+        let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+        value.serialize(&mut serializer)?;
+    }
+    json.push(b'\n');
+    // serde_json only writes valid UTF-8
+    Ok(String::from_utf8(json).expect("serde_json wrote invalid UTF-8"))
+}
 
-    fs::create_dir_all(outdir)?;
+/// Whether `path` is to be written under [`GenerateOptions::overwrite`], failing if it exists
+/// and existing files are kept
+fn may_write(path: &Path, options: &GenerateOptions) -> Result<bool> {
+    if !path.exists() {
+        return Ok(true);
+    }
+    match options.overwrite {
+        Overwrite::Error => anyhow::bail!(
+            "not writing `{}`: the file already exists, use --force to overwrite it or \
+             --no-clobber to skip it",
+            path.display()
+        ),
+        Overwrite::Skip => {
+            if !options.quiet {
+                eprintln!("Skipping `{}`: the file already exists", path.display());
+            }
+            Ok(false)
+        }
+        Overwrite::Force => Ok(true),
+    }
+}
+
+/// Serialize `package` and write it to `path`, attributing any failure to the package and file
+///
+/// Returns whether the file was written, which it isn't when it exists and the options ask to
+/// skip existing files.
+fn write_package(package: &cps::Package, path: &Path, options: &GenerateOptions) -> Result<bool> {
+    if !may_write(path, options)
+        .with_context(|| format!("failed to write package `{}`", package.name))?
+    {
+        return Ok(false);
+    }
+    let json = to_json(package, options).with_context(|| {
+        format!(
+            "failed to serialize package `{}` for `{}`",
+            package.name,
+            path.display()
+        )
+    })?;
+    write_atomically(path, &json).with_context(|| {
+        format!(
+            "failed to write package `{}` to `{}`",
+            package.name,
+            path.display()
+        )
+    })?;
+    Ok(true)
+}
+
+/// Write `contents` to a temporary file next to `path` and rename it into place, so readers
+/// of `path` never see a partially written file
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let filename = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no filename")
+    })?;
+    let mut temp_filename = std::ffi::OsString::from(".");
+    temp_filename.push(filename);
+    temp_filename.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_filename);
+
+    let result = fs::write(&temp_path, contents).and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Read, parse, convert and validate a single pc file
+fn generate_package(
+    pc_filepath: &Path,
+    options: &GenerateOptions,
+    resolver: &dyn LibraryResolver,
+) -> Result<cps::Package> {
+    let data = fs::read_to_string(pc_filepath)?;
+    let pkg_config = parse_pkg_config(pc_filepath, &data, options)?;
+    let package = convert_with(pkg_config, options, resolver)?;
+    package.validate()?;
+    Ok(package)
+}
+
+/// Convert each pc file to a validated CPS package in memory, a failure to convert one file
+/// doesn't stop the others
+///
+/// This is the pipeline every generate command is built on, the commands only write the
+/// returned packages. Library lookups are cached across the files.
+pub fn convert_all(
+    pc_files: &[PathBuf],
+    options: &GenerateOptions,
+) -> Vec<(PathBuf, Result<cps::Package>)> {
+    let resolver = CachedResolver::new(options.library_search.clone());
+    let progress = Progress::new(pc_files.len(), options.progress && !options.quiet);
+    let results = pc_files
+        .iter()
+        .map(|path| {
+            progress.start(&path.display().to_string());
+            let result = generate_package(path, options, &resolver)
+                .with_context(|| format!("failed to convert `{}`", path.display()));
+            progress.inc();
+            (path.clone(), result)
+        })
+        .collect();
+    progress.finish();
+    results
+}
+
+/// Path in `outdir` of the cps file generated from `pc_filepath`
+fn cps_filepath_in(outdir: &Path, pc_filepath: &Path) -> Result<PathBuf> {
+    let pc_filename = pc_filepath
+        .file_name()
+        .context("error getting filename of pc file")?
+        .to_str()
+        .context("error converting OsStr to str")?;
+    Ok(outdir.join(pc_filename.replace(".pc", ".cps")))
+}
 
-    for path in pc_files {
-        dbg!(&path);
-        let pc_filename = path
-            .file_name()
-            .context("error getting filename of pc file")?
-            .to_str()
-            .context("error converting OsStr to str")?
-            .to_string();
-        let data = std::fs::read_to_string(path)?;
-        let pkg_config = match pkg_config::PkgConfigFile::parse(&data) {
-            Ok(pkg_config) => pkg_config,
-            Err(error) => {
-                eprintln!("Error:\n{}", error);
-                continue;
+/// A cps file generated by [`generate_all`]
+#[derive(Debug)]
+struct Generated {
+    path: PathBuf,
+    /// Whether the file was written, it isn't when it exists and the options ask to skip
+    /// existing files
+    written: bool,
+}
+
+/// Convert each pc file into `outdir`, returning the cps file of each
+///
+/// A pc file for a package name and version, or for a cps file, that was already converted
+/// from an earlier file (e.g. an identical copy in another lib dir) is skipped and left out of
+/// the results. Unless
+/// the options ask to skip or overwrite existing files, nothing is written if any cps file, or
+/// the [`INDEX_FILENAME`] with [`GenerateOptions::index`], already exists.
+fn generate_all(
+    pc_files: &[PathBuf],
+    outdir: &Path,
+    options: &GenerateOptions,
+) -> Result<Vec<(PathBuf, Result<Generated>)>> {
+    let mut converted = HashMap::<_, PathBuf>::new();
+    let mut targets = HashMap::<PathBuf, PathBuf>::new();
+    let packages: Vec<_> = convert_all(pc_files, options)
+        .into_iter()
+        .filter(|(path, package)| {
+            let Ok(package) = package else {
+                return true;
+            };
+            let key = (package.name.clone(), package.version.clone());
+            if let Some(first) = converted.get(&key) {
+                if !options.quiet {
+                    eprintln!(
+                        "Skipping `{}`: package `{}` was already converted from `{}`",
+                        path.display(),
+                        package.name,
+                        first.display()
+                    );
+                }
+                return false;
             }
-        };
-        let cps_package: cps::Package = match pkg_config.try_into() {
-            Ok(cps) => cps,
-            Err(error) => {
-                eprintln!("Error:\n{}", error);
-                continue;
+            // A pc file of the same filename found later, e.g. another version of the package
+            // in a lib dir further down the search path, would replace the first one's cps file
+            if let Ok(target) = cps_filepath_in(outdir, path) {
+                if let Some(first) = targets.get(&target) {
+                    if !options.quiet {
+                        eprintln!(
+                            "Skipping `{}`: `{}` was already converted from `{}`",
+                            path.display(),
+                            target.display(),
+                            first.display()
+                        );
+                    }
+                    return false;
+                }
+                targets.insert(target, path.clone());
             }
-        };
-        let json = serde_json::to_string_pretty(&cps_package)?;
-        let cps_filename = pc_filename.replace(".pc", ".cps");
-        std::fs::write(outdir.join(cps_filename), json)?;
+            converted.insert(key, path.clone());
+            true
+        })
+        .collect();
+
+    if options.overwrite == Overwrite::Error {
+        let existing: Vec<_> = packages
+            .iter()
+            .filter(|(_, package)| package.is_ok())
+            .filter_map(|(path, _)| cps_filepath_in(outdir, path).ok())
+            .chain(options.index.then(|| outdir.join(INDEX_FILENAME)))
+            .filter(|cps_filepath| cps_filepath.exists())
+            .collect();
+        anyhow::ensure!(
+            existing.is_empty(),
+            "not writing any package: {} already exist, use --force to overwrite them or \
+             --no-clobber to skip them",
+            existing
+                .iter()
+                .map(|path| format!("`{}`", path.display()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(packages
+        .into_iter()
+        .map(|(path, package)| {
+            let result = package.and_then(|package| {
+                let cps_filepath = cps_filepath_in(outdir, &path)?;
+                let written = write_package(&package, &cps_filepath, options)
+                    .with_context(|| format!("failed to convert `{}`", path.display()))?;
+                Ok(Generated {
+                    path: cps_filepath,
+                    written,
+                })
+            });
+            if let Err(error) = &result {
+                eprintln!("Error:\n{:#}", error);
+            }
+            (path, result)
+        })
+        .collect())
+}
+
+/// Name of the index of the packages generated into an output directory
+pub const INDEX_FILENAME: &str = "index.json";
+
+/// Where a package of the index was written
+#[serde_with::skip_serializing_none]
+#[derive(serde::Serialize, Debug)]
+struct IndexEntry {
+    version: Option<String>,
+    /// The cps file relative to the output directory
+    file: PathBuf,
+}
+
+/// Write the index of the `cps_files` in `outdir`, mapping each package name to its version
+/// and file, e.g. `{ "fcl": { "version": "0.7.0", "file": "fcl.cps" } }`
+///
+/// When several files hold versions of the same package the first one is indexed, with a
+/// warning naming the others. An existing index is handled like the cps files, see
+/// [`GenerateOptions::overwrite`].
+fn write_index(outdir: &Path, cps_files: &[PathBuf], options: &GenerateOptions) -> Result<()> {
+    let path = outdir.join(INDEX_FILENAME);
+    if !may_write(&path, options)? {
+        return Ok(());
+    }
+    let mut index = BTreeMap::<String, IndexEntry>::new();
+    for cps_file in dedup(cps_files) {
+        let package = cps::load_cps(cps_file)
+            .with_context(|| format!("failed to index `{}`", cps_file.display()))?;
+        let file = cps_file
+            .strip_prefix(outdir)
+            .unwrap_or(cps_file)
+            .to_path_buf();
+        match index.entry(package.name) {
+            btree_map::Entry::Occupied(entry) => eprintln!(
+                "Warning: not indexing version {} of package `{}` in `{}`, version {} in `{}` \
+                 is indexed",
+                package.version.as_deref().unwrap_or("(none)"),
+                entry.key(),
+                file.display(),
+                entry.get().version.as_deref().unwrap_or("(none)"),
+                entry.get().file.display()
+            ),
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(IndexEntry {
+                    version: package.version,
+                    file,
+                });
+            }
+        }
+    }
+    write_atomically(&path, &to_json(&index, options)?)
+        .with_context(|| format!("failed to write `{}`", path.display()))
+}
+
+/// Convert the discovered pc files into `outdir`
+pub fn generate_all_from_pkg_config(
+    discovery: &discovery::DiscoveryOptions,
+    outdir: &Path,
+    options: &GenerateOptions,
+) -> Result<()> {
+    let pc_files = discovery::discover(discovery)?;
+
+    fs::create_dir_all(outdir)?;
+    let results = generate_all(&pc_files, outdir, options)?;
+
+    let generated: Vec<_> = results
+        .into_iter()
+        .filter_map(|(_, result)| result.ok())
+        .collect();
+    // A run that wrote nothing leaves the index of an earlier run as it is
+    if options.index && generated.iter().any(|generated| generated.written) {
+        let cps_files: Vec<_> = generated
+            .into_iter()
+            .map(|generated| generated.path)
+            .collect();
+        write_index(outdir, &cps_files, options)?;
     }
+    Ok(())
+}
 
+/// Check that `package` reads back from its JSON as the same valid package
+fn round_trip(package: &cps::Package, options: &GenerateOptions) -> Result<()> {
+    let json = to_json(package, options)?;
+    let read_back: cps::Package = json.parse().context("the package read back is invalid")?;
+    anyhow::ensure!(
+        read_back == *package,
+        "the package read back differs from the package written:\n{}",
+        json
+    );
     Ok(())
 }
 
-pub fn generate_from_pkg_config(pc_filepath: &Path, cps_filepath: &Path) -> Result<()> {
-    let data = std::fs::read_to_string(pc_filepath)?;
-    let pkg_config = pkg_config::PkgConfigFile::parse(&data)?;
-    let cps_package: cps::Package = pkg_config.try_into()?;
-    let json = serde_json::to_string_pretty(&cps_package)?;
-    std::fs::write(cps_filepath, json)?;
+/// Convert the discovered pc files and check that each package survives a round trip through
+/// JSON, printing the files that fail and then how many passed and failed
+///
+/// Fails if any file fails to convert or to round trip.
+pub fn selftest(discovery: &discovery::DiscoveryOptions, options: &GenerateOptions) -> Result<()> {
+    let pc_files = discovery::discover(discovery)?;
+    let mut failed = 0;
+    for (path, package) in convert_all(&pc_files, options) {
+        let result = package.and_then(|package| {
+            round_trip(&package, options)
+                .with_context(|| format!("failed to round trip `{}`", path.display()))
+        });
+        if let Err(error) = result {
+            eprintln!("Error:\n{:#}", error);
+            failed += 1;
+        }
+    }
+    println!("{} passed, {} failed", pc_files.len() - failed, failed);
+    anyhow::ensure!(
+        failed == 0,
+        "{} of {} pc files failed the round trip",
+        failed,
+        pc_files.len()
+    );
+    Ok(())
+}
+
+/// Convert exactly the pc file at `pc_filepath` and write it to `cps_filepath`
+///
+/// With [`GenerateOptions::explain`] how the file is converted is printed to stderr first.
+pub fn generate_one(
+    pc_filepath: &Path,
+    cps_filepath: &Path,
+    options: &GenerateOptions,
+) -> Result<()> {
+    let package = if options.explain {
+        let mut explain_options = options.clone();
+        explain_options.parse.record_sources = true;
+        let data = fs::read_to_string(pc_filepath)?;
+        let (package, explanation) = parse_pkg_config(pc_filepath, &data, &explain_options)
+            .and_then(|pkg_config| {
+                convert_explained(pkg_config, &explain_options, &options.library_search)
+            })
+            .with_context(|| format!("failed to convert `{}`", pc_filepath.display()))?;
+        eprint!("{}", explanation);
+        package.validate()?;
+        package
+    } else {
+        let (_, package) = convert_all(&[pc_filepath.to_path_buf()], options)
+            .pop()
+            .expect("one result per pc file");
+        package?
+    };
+    write_package(&package, cps_filepath, options)?;
+    Ok(())
+}
+
+#[test]
+fn test_requires_internal_excluded_by_default() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar
+Requires.internal: gtest >= 1.10
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["requires"],
+        serde_json::json!(["bar"])
+    );
+    assert_eq!(json["requires"], serde_json::json!({ "bar": {} }));
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions {
+            include_requires_internal: true,
+            ..GenerateOptions::default()
+        },
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["requires"],
+        serde_json::json!(["bar", "gtest"])
+    );
+    assert_eq!(json["requires"]["gtest"]["version"], "1.10");
+    Ok(())
+}
+
+#[test]
+fn test_libs_private() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    dir.write("lib/libfoo.a", "");
+    dir.write("lib/libcps-deps-private.a", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+         Cflags: -I/usr/include/foo\nLibs: -L{0} -lfoo\n\
+         Libs.private: -L{0} -lcps-deps-private -lz-cps-deps-missing\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["link_requires"],
+        serde_json::json!([":cps-deps-private"])
+    );
+    assert_eq!(
+        json["components"]["foo"]["link_libraries"],
+        serde_json::json!(["z-cps-deps-missing"])
+    );
+    assert_eq!(json["components"]["cps-deps-private"]["type"], "archive");
+    // private libraries are not required to compile against the package
+    assert_eq!(
+        json["components"]["foo"]["requires"],
+        serde_json::Value::Null
+    );
+    package.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_header_only() -> Result<()> {
+    struct NoSearch;
+
+    impl LibraryResolver for NoSearch {
+        fn find(&self, library: &str, _: &[PathBuf]) -> Result<LibraryLocation, Error> {
+            panic!("searched for {} in a header-only package", library)
+        }
+    }
+
+    let data = "prefix=/usr\nincludedir=${prefix}/include\n\
+                Name: Eigen3\nDescription: A C++ template library for linear algebra\n\
+                Version: 3.4.0\nLibs: -L/usr/lib/cps-deps-missing\nCflags: -I${includedir}/eigen3\n";
+
+    let package = convert_with(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+        &NoSearch,
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"],
+        serde_json::json!({
+            "Eigen3": {
+                "type": "interface",
+                "includes": { "*": ["/usr/include/eigen3"] }
+            }
+        })
+    );
+    assert_eq!(json["default_components"], serde_json::json!(["Eigen3"]));
+    package.validate()?;
+
+    // nor are its private libraries searched for
+    let package = convert_with(
+        pkg_config::PkgConfigFile::parse(&format!("{}Libs.private: -lm\n", data))?,
+        &GenerateOptions::default(),
+        &NoSearch,
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["Eigen3"]["type"], "interface");
+    assert_eq!(
+        json["components"]["Eigen3"]["link_libraries"],
+        serde_json::json!(["m"])
+    );
+    package.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_plugin_modules() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let plugin = dir.write("lib/foo/plugins/libcps-deps-plugin.so", "");
+    let data = format!(
+        "Name: foo-plugin\nDescription: Foo plugin\nVersion: 1.0.0\n\
+         Libs: -L{} -lcps-deps-plugin\n",
+        dir.path().join("lib/foo/plugins").display()
+    );
+
+    let options = GenerateOptions {
+        plugin_modules: true,
+        ..GenerateOptions::default()
+    };
+    let package = convert(pkg_config::PkgConfigFile::parse(&data)?, &options)?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["cps-deps-plugin"]["type"], "module");
+    assert_eq!(
+        json["components"]["cps-deps-plugin"]["location"],
+        plugin.to_str().unwrap()
+    );
+    package.validate()?;
+
+    // without the option it is linked like any shared library
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["cps-deps-plugin"]["type"], "dylib");
+
+    assert!(is_plugin_location("/usr/lib/gio/modules/libgiognutls.so"));
+    assert!(!is_plugin_location("/usr/lib/libplugins.so"));
+    Ok(())
+}
+
+#[test]
+fn test_partially_found_libraries() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let found = dir.write("lib/libcps-deps-found.a", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+         Libs: -L{} -lcps-deps-found -lcps-deps-missing\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["cps-deps-found"]["type"], "archive");
+    assert_eq!(
+        json["components"]["cps-deps-found"]["location"],
+        found.to_str().unwrap()
+    );
+    assert_eq!(
+        json["components"]["cps-deps-found"]["link_libraries"],
+        serde_json::json!(["cps-deps-missing"])
+    );
+    package.validate()?;
+
+    // With none of the libraries found the package is not installed
+    let data = data.replace("-lcps-deps-found ", "");
+    assert!(matches!(
+        convert(
+            pkg_config::PkgConfigFile::parse(&data)?,
+            &GenerateOptions::default(),
+        ),
+        Err(Error::LibraryNotFound { library, .. }) if library == "cps-deps-missing"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_cflags_private() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo
+Cflags.private: -I/opt/inc -DPRIV
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["includes"],
+        serde_json::json!({ "*": ["/usr/include/foo"] })
+    );
+    assert_eq!(
+        json["components"]["foo"]["definitions"],
+        serde_json::Value::Null
+    );
+    assert_eq!(
+        json["components"]["foo-private"],
+        serde_json::json!({
+            "type": "interface",
+            "requires": [":foo"],
+            "definitions": { "*": ["PRIV"] },
+            "includes": { "*": ["/opt/inc"] }
+        })
+    );
+    assert_eq!(package.default_components, Some(vec!["foo".to_string()]));
+    Ok(())
+}
+
+#[test]
+fn test_requires_private() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar
+Requires.private: uuid expat >= 2.2, bar
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["requires"],
+        serde_json::json!({ "bar": {}, "uuid": {}, "expat": { "version": "2.2" } })
+    );
+    assert_eq!(
+        json["components"]["foo"]["requires"],
+        serde_json::json!(["bar"])
+    );
+    assert_eq!(
+        json["components"]["foo"]["link_requires"],
+        serde_json::json!(["uuid", "expat"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_exact_filename_library() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let exact = dir.write("lib/libcps-deps-exact.so.1", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\nLibs: -L{} -l:libcps-deps-exact.so.1\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        package.default_components,
+        Some(vec!["cps-deps-exact".to_string()])
+    );
+    assert_eq!(
+        json["components"]["cps-deps-exact"],
+        serde_json::json!({
+            "type": "dylib",
+            "location": exact.to_str().unwrap(),
+            "link_location": exact.to_str().unwrap()
+        })
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_compat_version_from_soname() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let real = dir.write("lib/libcps-deps-compat.so.2", "");
+    std::os::unix::fs::symlink(&real, dir.path().join("lib/libcps-deps-compat.so"))?;
+    let parse = |version: &str| {
+        pkg_config::PkgConfigFile::parse(&format!(
+            "Name: foo\nDescription: Foo library\nVersion: {}\nLibs: -L{} -lcps-deps-compat\n",
+            version,
+            dir.path().join("lib").display()
+        ))
+    };
+
+    let package = convert(parse("2.3.1")?, &GenerateOptions::default())?;
+    assert_eq!(package.compat_version, Some("2".to_string()));
+
+    // The soname of a library not versioned like its package says nothing about the package
+    let package = convert(parse("1.6.37")?, &GenerateOptions::default())?;
+    assert_eq!(package.compat_version, None);
+    Ok(())
+}
+
+#[cfg(feature = "soname")]
+#[test]
+fn test_dylib_component_named_from_soname() -> Result<()> {
+    if std::process::Command::new("readelf")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        return Ok(());
+    }
+    // Linked as `cps-alias` and `cps-alias-private`, both with the SONAME `libcps-fixture.so.3`
+    let dir = crate::test_util::TempDir::new();
+    let fixture = include_bytes!("../testdata/libcps-fixture.so");
+    fs::create_dir_all(dir.path().join("lib"))?;
+    fs::write(dir.path().join("lib/libcps-alias.so"), fixture)?;
+    fs::write(dir.path().join("lib/libcps-alias-private.so"), fixture)?;
+    dir.write("lib/libcps-deps-other.so", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\n\
+         Libs: -L{0} -lcps-deps-other -lcps-alias\nLibs.private: -L{0} -lcps-alias-private\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["cps-fixture"]["type"], "dylib");
+    assert_eq!(
+        json["components"]["cps-deps-other"]["requires"],
+        serde_json::json!([":cps-fixture"])
+    );
+    // the SONAME of the private library is taken, so it keeps the name it is linked by
+    assert_eq!(json["components"]["cps-alias-private"]["type"], "dylib");
+    assert_eq!(
+        json["components"]["cps-deps-other"]["link_requires"],
+        serde_json::json!([":cps-alias-private"])
+    );
+    assert_eq!(json["components"].get("cps-alias"), None);
+    package.validate()?;
+
+    // the default component is named by its SONAME too
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nLibs: -L{} -lcps-alias\n",
+        dir.path().join("lib").display()
+    );
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert_eq!(
+        package.default_components,
+        Some(vec!["cps-fixture".to_string()])
+    );
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["components"]["cps-fixture"]["type"], "dylib");
+    package.validate()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dylib_link_location() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let real = dir.write("lib/libcps-deps-link.so.1.2.3", "");
+    let link = dir.path().join("lib/libcps-deps-link.so");
+    std::os::unix::fs::symlink(&real, &link)?;
+    let real = fs::canonicalize(&real)?;
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.2.3\nLibs: -L{} -lcps-deps-link\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["cps-deps-link"],
+        serde_json::json!({
+            "type": "dylib",
+            "location": real.to_str().unwrap(),
+            "link_location": link.to_str().unwrap()
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_shared_and_static_locations() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let dylib = dir.write("lib/libcps-deps-both.so", "");
+    let archive = dir.write("lib/libcps-deps-both.a", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\nLibs: -L{} -lcps-deps-both\n",
+        dir.path().join("lib").display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["cps-deps-both-shared"],
+        serde_json::json!({ "type": "dylib", "location": dylib.to_str().unwrap() })
+    );
+    assert_eq!(
+        json["components"]["cps-deps-both-static"],
+        serde_json::json!({ "type": "archive", "location": archive.to_str().unwrap() })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_frameworks() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo
+Libs: -framework CoreFoundation -Wl,-dead_strip -framework Security
+    "#;
+
+    let pkg_config = pkg_config::PkgConfigFile::parse(data)?;
+    assert_eq!(
+        pkg_config.frameworks,
+        vec!["CoreFoundation".to_string(), "Security".to_string()]
+    );
+    let package = convert(pkg_config, &GenerateOptions::default())?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["link_flags"],
+        serde_json::json!([
+            "-framework",
+            "CoreFoundation",
+            "-Wl,-dead_strip",
+            "-framework",
+            "Security"
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_default_component_named_after_package() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/libcps-fixture.so"),
+        dir.path().join("libcps-fixture.so"),
+    )?;
+    let data = format!(
+        "Name: CPS Fixture\nDescription: Fixture library\nVersion: 3.1\nLibs: -L{} -lcps-fixture\n",
+        dir.path().display()
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert_eq!(
+        package.default_components,
+        Some(vec!["cps-fixture".to_string()])
+    );
+
+    let options = GenerateOptions {
+        name_default_component_after_package: true,
+        ..GenerateOptions::default()
+    };
+    let package = convert(pkg_config::PkgConfigFile::parse(&data)?, &options)?;
+    assert_eq!(
+        package.default_components,
+        Some(vec!["CPS_Fixture".to_string()])
+    );
+    let mut names: Vec<_> = package.components.keys().collect();
+    names.sort();
+    assert_eq!(names, ["CPS_Fixture"]);
+    assert_eq!(package.components["CPS_Fixture"].type_name(), "dylib");
+
+    // Another library named like the package keeps its component, so the first library keeps
+    // its own name
+    dir.write("libcps-fixture-extra.a", "");
+    let data = format!(
+        "Name: cps-fixture-extra\nDescription: Fixture library\nVersion: 3.1\n\
+         Libs: -L{} -lcps-fixture -lcps-fixture-extra\n",
+        dir.path().display()
+    );
+    let package = convert(pkg_config::PkgConfigFile::parse(&data)?, &options)?;
+    assert_eq!(
+        package.default_components,
+        Some(vec!["cps-fixture".to_string()])
+    );
+    let mut names: Vec<_> = package.components.keys().collect();
+    names.sort();
+    assert_eq!(names, ["cps-fixture", "cps-fixture-extra"]);
+    assert_eq!(package.components["cps-fixture"].type_name(), "dylib");
+    assert_eq!(
+        package.components["cps-fixture-extra"].type_name(),
+        "archive"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_empty_version() -> Result<()> {
+    let data = "Name: foo\nDescription: Foo library\nVersion:\nCflags: -I/usr/include/foo\n";
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert_eq!(package.version, None);
+    Ok(())
+}
+
+#[test]
+fn test_link_flags_keep_source_order() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Libs: -Wl,--as-needed -L/opt/lib -Wl,-rpath,/opt/lib -framework Foo -pthread -Wl,--no-as-needed
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["link_flags"],
+        serde_json::json!([
+            "-Wl,--as-needed",
+            "-Wl,-rpath,/opt/lib",
+            "-framework",
+            "Foo",
+            "-pthread",
+            "-Wl,--no-as-needed"
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_version_range() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar >= 1.0, bar < 2.0, baz >= 3
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["requires"]["bar"],
+        serde_json::json!({
+            "version": "1.0",
+            "x-pkgconfig-version-constraints": [">= 1.0", "< 2.0"]
+        })
+    );
+    assert_eq!(
+        json["requires"]["baz"],
+        serde_json::json!({ "version": "3" })
+    );
+    assert_eq!(
+        json["components"]["foo"]["requires"],
+        serde_json::json!(["bar", "baz"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_provides() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Provides: foo-alias = 1.0, foo-compat, foo
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo-alias"],
+        serde_json::json!({
+            "type": "symbolic",
+            "requires": [":foo"],
+            "x-pkgconfig-version-constraints": ["= 1.0"]
+        })
+    );
+    assert_eq!(
+        json["components"]["foo-compat"],
+        serde_json::json!({ "type": "symbolic", "requires": [":foo"] })
+    );
+    assert_eq!(json["components"]["foo"]["type"], "interface");
+    assert_eq!(package.components.len(), 3);
+    package.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_license() -> Result<()> {
+    let data = "Name: foo\nDescription: Foo library\nVersion: 1.0\nLicense: Apache 2.0\n";
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert_eq!(package.license.as_deref(), Some("Apache 2.0"));
+
+    let options = GenerateOptions {
+        spdx_license: true,
+        quiet: true,
+        ..GenerateOptions::default()
+    };
+    let package = convert(pkg_config::PkgConfigFile::parse(data)?, &options)?;
+    assert_eq!(package.license.as_deref(), Some("Apache-2.0"));
+    assert_eq!(serde_json::to_value(&package)?["license"], "Apache-2.0");
+
+    let data = data.replace("Apache 2.0", "Foo Corp Proprietary");
+    let package = convert(pkg_config::PkgConfigFile::parse(&data)?, &options)?;
+    assert_eq!(package.license.as_deref(), Some("Foo Corp Proprietary"));
+    Ok(())
+}
+
+#[test]
+fn test_provenance() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let archive = dir.write("lib/libcps-deps-provenance.a", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+         Cflags: -isystem /opt/foo/include -DFOO=1 -std=c++17 -Wall\n\
+         Libs: -L{} -lcps-deps-provenance -lcps-deps-missing\n",
+        dir.path().join("lib").display()
+    );
+    let parse = pkg_config::ParseOptions {
+        record_sources: true,
+        ..pkg_config::ParseOptions::default()
+    };
+    let (pkg_config, _) = pkg_config::PkgConfigFile::parse_with_options(&data, &parse, None)?;
+
+    let (package, provenance) = convert_with_provenance(pkg_config, &GenerateOptions::default())?;
+    let source_of = |attribute: &str, value: &str| {
+        provenance
+            .iter()
+            .find(|provenance| provenance.attribute == attribute && provenance.value == value)
+            .map(|provenance| {
+                assert_eq!(provenance.component, "cps-deps-provenance");
+                (
+                    provenance.source.property.as_str(),
+                    provenance.source.flag.as_str(),
+                )
+            })
+    };
+    assert_eq!(
+        source_of("includes", "/opt/foo/include"),
+        Some(("Cflags", "-isystem /opt/foo/include"))
+    );
+    assert_eq!(
+        source_of("definitions", "FOO=1"),
+        Some(("Cflags", "-DFOO=1"))
+    );
+    assert_eq!(
+        source_of("compile_features", "c++17"),
+        Some(("Cflags", "-std=c++17"))
+    );
+    assert_eq!(
+        source_of("compile_flags", "-Wall"),
+        Some(("Cflags", "-Wall"))
+    );
+    assert_eq!(
+        source_of("location", archive.to_str().unwrap()),
+        Some(("Libs", "-lcps-deps-provenance"))
+    );
+    assert_eq!(
+        source_of("link_libraries", "cps-deps-missing"),
+        Some(("Libs", "-lcps-deps-missing"))
+    );
+    package.validate()?;
+
+    // A value is credited to the flag it was converted from, not the first one that could have
+    // produced it
+    let dotted = dir.write("lib/libcps-deps-provenance.x.a", "");
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+         Cflags: -pthread\n\
+         Libs: -L{} -lcps-deps-provenance -lcps-deps-provenance.x -pthread\n",
+        dir.path().join("lib").display()
+    );
+    let (pkg_config, _) = pkg_config::PkgConfigFile::parse_with_options(&data, &parse, None)?;
+    let (_, provenance) = convert_with_provenance(pkg_config, &GenerateOptions::default())?;
+    let source_of = |component: &str, attribute: &str| {
+        provenance
+            .iter()
+            .find(|provenance| {
+                provenance.component == component && provenance.attribute == attribute
+            })
+            .map(|provenance| {
+                (
+                    provenance.value.as_str(),
+                    provenance.source.property.as_str(),
+                    provenance.source.flag.as_str(),
+                )
+            })
+    };
+    assert_eq!(
+        source_of("cps-deps-provenance.x", "location"),
+        Some((dotted.to_str().unwrap(), "Libs", "-lcps-deps-provenance.x"))
+    );
+    assert_eq!(
+        source_of("cps-deps-provenance", "compile_flags"),
+        Some(("-pthread", "Cflags", "-pthread"))
+    );
+    assert_eq!(
+        source_of("cps-deps-provenance", "link_flags"),
+        Some(("-pthread", "Libs", "-pthread"))
+    );
+
+    // Without the sources there is nothing to trace the values back to
+    let (_, provenance) = convert_with_provenance(
+        pkg_config::PkgConfigFile::parse(&data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert!(provenance.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_explain() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let dylib = dir.path().join("libcps-fixture.so");
+    fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/libcps-fixture.so"),
+        &dylib,
+    )?;
+    let data = format!(
+        "Name: fixture\nDescription: Fixture library\nVersion: 3.1\n\
+         Requires: bar\nRequires.private: baz\nRequires.internal: gtest\n\
+         Cflags: -std=c++17 -I/usr/include/fixture\n\
+         Libs: -L{} -lcps-fixture -lcps-deps-missing\n",
+        dir.path().display()
+    );
+    let options = GenerateOptions {
+        parse: pkg_config::ParseOptions {
+            record_sources: true,
+            ..pkg_config::ParseOptions::default()
+        },
+        ..GenerateOptions::default()
+    };
+
+    let explanation = explain(
+        pkg_config::PkgConfigFile::parse_with_options(&data, &options.parse, None)?.0,
+        &options,
+    )?;
+    assert!(
+        explanation.contains(&format!(
+            "Component `cps-fixture` is a dylib: `-lcps-fixture` of `Libs` resolved to the shared \
+             library `{}`",
+            dylib.display()
+        )),
+        "{}",
+        explanation
+    );
+    for line in [
+        "Flag `-std=c++17` of `Cflags` became the compile feature `c++17`",
+        "Library `-lcps-deps-missing` of `Libs` was not found and is left to the linker",
+        "Dependency `bar` of `Requires` is kept as a requirement",
+        "Dependency `baz` of `Requires.private` is kept as a link requirement",
+        "Dependency `gtest` of `Requires.internal` is dropped",
+    ] {
+        assert!(explanation.contains(line), "{}", explanation);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_extra_properties() -> Result<()> {
+    let data = "Name: foo\nDescription: Foo library\nVersion: 1.0.0\n\
+                X-Homepage: https://example.com/foo\n";
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    assert_eq!(
+        package.extra[PROPERTIES_KEY],
+        serde_json::json!({ "X-Homepage": "https://example.com/foo" })
+    );
+    package.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_url_and_maintainer() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+URL: https://example.com/foo
+Maintainer: Foo Developers <foo@example.com>
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(json["x-pkgconfig-url"], "https://example.com/foo");
+    assert_eq!(
+        json["x-pkgconfig-maintainer"],
+        "Foo Developers <foo@example.com>"
+    );
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse("Name: foo\nDescription: Foo\nVersion: 1.0\n")?,
+        &GenerateOptions::default(),
+    )?;
+    assert!(package.extra.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_conflicts() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Conflicts: bar < 2.0
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["x-pkgconfig-conflicts"],
+        serde_json::json!([
+            { "name": "bar", "constraints": [{ "op": "<", "version": "2.0" }] }
+        ])
+    );
+    // the extension survives reading the generated file back
+    let package: cps::Package = json.to_string().parse()?;
+    assert_eq!(package.extra[CONFLICTS_KEY], json["x-pkgconfig-conflicts"]);
+    Ok(())
+}
+
+#[test]
+fn test_link_languages() -> Result<()> {
+    let pc = |cflags: &str| {
+        format!(
+            "Name: foo\nDescription: Foo library\nVersion: 1.0.0\nCflags: {}\n",
+            cflags
+        )
+    };
+    let link_languages = |cflags: &str| -> Result<serde_json::Value> {
+        let package = convert(
+            pkg_config::PkgConfigFile::parse(&pc(cflags))?,
+            &GenerateOptions::default(),
+        )?;
+        Ok(serde_json::to_value(&package)?["components"]["foo"]["link_languages"].clone())
+    };
+
+    assert_eq!(
+        link_languages("-I/usr/include/foo -std=c++17")?,
+        serde_json::json!(["c++"])
+    );
+    assert_eq!(link_languages("-std=gnu++14")?, serde_json::json!(["c++"]));
+    assert_eq!(
+        link_languages("-I/usr/include/foo -std=c99")?,
+        serde_json::Value::Null
+    );
+    Ok(())
+}
+
+#[test]
+fn test_compile_features() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo -std=c++17 -fexceptions -Wall
+    "#;
+    let mut options = GenerateOptions::default();
+    options.features.register("-fexceptions", "exceptions");
+
+    let package = convert(pkg_config::PkgConfigFile::parse(data)?, &options)?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["compile_features"],
+        serde_json::json!(["c++17", "exceptions"])
+    );
+    assert_eq!(
+        json["components"]["foo"]["compile_flags"],
+        serde_json::json!({ "*": ["-Wall"] })
+    );
+    assert_eq!(
+        json["components"]["foo"]["link_languages"],
+        serde_json::json!(["c++"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_duplicates_dropped() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include -DFOO -I/usr/include -Wall -DFOO -Wall
+Libs: -Xlinker -rpath -Xlinker /opt/lib -Xlinker -rpath -framework Cocoa -framework Cocoa
+    "#;
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    let component = &json["components"]["foo"];
+    assert_eq!(
+        component["includes"],
+        serde_json::json!({ "*": ["/usr/include"] })
+    );
+    assert_eq!(
+        component["definitions"],
+        serde_json::json!({ "*": ["FOO"] })
+    );
+    assert_eq!(
+        component["compile_flags"],
+        serde_json::json!({ "*": ["-Wall"] })
+    );
+    assert_eq!(
+        component["link_flags"],
+        serde_json::json!([
+            "-Xlinker",
+            "-rpath",
+            "-Xlinker",
+            "/opt/lib",
+            "-framework",
+            "Cocoa"
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(normalize_path("/usr//include/", false), "/usr/include");
+    assert_eq!(
+        normalize_path("/usr/lib/../include/./foo", false),
+        "/usr/include/foo"
+    );
+    assert_eq!(normalize_path("/../usr", false), "/usr");
+    assert_eq!(normalize_path("../foo/../../bar", false), "../../bar");
+    assert_eq!(normalize_path("foo/..", false), ".");
+    assert_eq!(
+        normalize_path("/does-not-exist//include/", true),
+        "/does-not-exist/include"
+    );
+}
+
+#[test]
+fn test_normalized_includes() -> Result<()> {
+    let data = r#"
+prefix=/usr//
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I${prefix}/include/ -I${prefix}/lib/../include/foo
+    "#;
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["includes"],
+        serde_json::json!({ "*": ["/usr/include", "/usr/include/foo"] })
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_canonicalized_includes() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let real = dir.path().join("real");
+    fs::create_dir_all(&real)?;
+    std::os::unix::fs::symlink(&real, dir.path().join("link"))?;
+    let real = fs::canonicalize(&real)?;
+
+    let data = format!(
+        "Name: foo\nDescription: Foo library\nVersion: 1.0.0\nCflags: -I{}/link/\n",
+        dir.path().display()
+    );
+    let options = GenerateOptions {
+        canonicalize: true,
+        ..GenerateOptions::default()
+    };
+    let package = convert(pkg_config::PkgConfigFile::parse(&data)?, &options)?;
+    let json = serde_json::to_value(&package)?;
+    assert_eq!(
+        json["components"]["foo"]["includes"],
+        serde_json::json!({ "*": [real.to_string_lossy()] })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_write_errors_are_attributed_to_the_file() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc_files = vec![
+        dir.write_pc("pc/bad.pc", "bad"),
+        dir.write_pc("pc/good.pc", "good"),
+    ];
+    // a directory in place of the output file makes writing `bad.cps` fail
+    let outdir = dir.path().join("out");
+    fs::create_dir_all(outdir.join("bad.cps"))?;
+
+    let options = GenerateOptions {
+        overwrite: Overwrite::Force,
+        ..GenerateOptions::default()
+    };
+    let results = generate_all(&pc_files, &outdir, &options)?;
+    assert_eq!(results.len(), 2);
+
+    let error = results[0]
+        .1
+        .as_ref()
+        .expect_err("writing bad.cps should fail");
+    let message = format!("{:#}", error);
+    assert!(message.contains("bad.pc"), "{}", message);
+    assert!(message.contains("package `bad`"), "{}", message);
+    assert!(message.contains("bad.cps"), "{}", message);
+
+    assert_eq!(
+        results[1].1.as_ref().ok().map(|generated| &generated.path),
+        Some(&outdir.join("good.cps"))
+    );
+    assert!(outdir.join("good.cps").is_file());
+    Ok(())
+}
+
+#[test]
+fn test_compact_output() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nRequires: bar >= 1.2\nCflags: -I/usr/include/foo -DFOO\n",
+    );
+    let cps = dir.path().join("foo.cps");
+    let options = GenerateOptions {
+        compact: true,
+        ..GenerateOptions::default()
+    };
+    generate_one(&pc, &cps, &options)?;
+
+    let json = fs::read_to_string(&cps)?;
+    assert_eq!(json.find('\n'), Some(json.len() - 1), "{}", json);
+    let written: cps::Package = json.parse()?;
+    let expected = generate_package(&pc, &options, &options.library_search)?;
+    assert_eq!(written, expected);
+    Ok(())
+}
+
+#[test]
+fn test_trailing_newline() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/usr/include/foo\n",
+    );
+    let cps = dir.path().join("foo.cps");
+    generate_one(&pc, &cps, &GenerateOptions::default())?;
+
+    let json = fs::read(&cps)?;
+    assert_eq!(json.last(), Some(&b'\n'));
+    assert!(!json.ends_with(b"\n\n"));
+    Ok(())
+}
+
+#[test]
+fn test_indent_output() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/usr/include/foo\n",
+    );
+    let cps = dir.path().join("foo.cps");
+    let options = GenerateOptions {
+        indent: "4".parse().map_err(anyhow::Error::msg)?,
+        ..GenerateOptions::default()
+    };
+    generate_one(&pc, &cps, &options)?;
+
+    let json = fs::read_to_string(&cps)?;
+    assert!(json.contains("\n    \"name\": \"foo\""), "{}", json);
+    assert!(json.contains("\n        \"foo\": {"), "{}", json);
+    assert!(!json.contains("\n  \""), "{}", json);
+
+    let options = GenerateOptions {
+        indent: "tab".parse().map_err(anyhow::Error::msg)?,
+        overwrite: Overwrite::Force,
+        ..GenerateOptions::default()
+    };
+    generate_one(&pc, &cps, &options)?;
+    let json = fs::read_to_string(&cps)?;
+    assert!(json.contains("\n\t\"name\": \"foo\""), "{}", json);
+    assert!("four".parse::<Indent>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_overwrite_is_atomic() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/usr/include/foo\n",
+    );
+    let cps = dir.write("out/foo.cps", "{ \"name\": \"truncated");
+
+    let options = GenerateOptions {
+        overwrite: Overwrite::Force,
+        ..GenerateOptions::default()
+    };
+    generate_one(&pc, &cps, &options)?;
+    let package: cps::Package = fs::read_to_string(&cps)?.parse()?;
+    assert_eq!(package.name, "foo");
+    let entries: Vec<_> = fs::read_dir(dir.path().join("out"))?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    assert_eq!(entries, ["foo.cps"]);
+    Ok(())
+}
+
+#[test]
+fn test_existing_files_are_not_overwritten() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc_files = vec![
+        dir.write_pc("pc/edited.pc", "edited"),
+        dir.write_pc("pc/new.pc", "new"),
+    ];
+    let edited = dir.write("out/edited.cps", "hand edited");
+    let outdir = dir.path().join("out");
+
+    let error = generate_all(&pc_files, &outdir, &GenerateOptions::default())
+        .expect_err("an existing cps file should stop the run");
+    let message = format!("{:#}", error);
+    assert!(message.contains("edited.cps"), "{}", message);
+    assert_eq!(fs::read_to_string(&edited)?, "hand edited");
+    assert!(!outdir.join("new.cps").exists());
+
+    let options = GenerateOptions {
+        overwrite: Overwrite::Skip,
+        ..GenerateOptions::default()
+    };
+    let results = generate_all(&pc_files, &outdir, &options)?;
+    assert_eq!(results.len(), 2);
+    let generated: Vec<_> = results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+        .map(|generated| (&generated.path, generated.written))
+        .collect();
+    assert_eq!(
+        generated,
+        [(&edited, false), (&outdir.join("new.cps"), true)]
+    );
+    assert_eq!(fs::read_to_string(&edited)?, "hand edited");
+    Ok(())
+}
+
+#[test]
+fn test_generate_only() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    for name in ["fcl", "foo", "bar"] {
+        dir.write_pc(format!("pc/{}.pc", name), name);
+    }
+    let discovery = discovery::DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        only: vec!["fcl".to_string()],
+        ..discovery::DiscoveryOptions::default()
+    };
+    let outdir = dir.path().join("out");
+
+    generate_all_from_pkg_config(&discovery, &outdir, &GenerateOptions::default())?;
+    let written: Vec<_> = fs::read_dir(&outdir)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    assert_eq!(written, ["fcl.cps"]);
+    Ok(())
+}
+
+#[test]
+fn test_generate_index() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    dir.write(
+        "pc/foo.pc",
+        "Name: foo\nDescription: Foo library\nVersion: 1.0\n",
+    );
+    dir.write(
+        "pc/bar-2.pc",
+        "Name: bar\nDescription: Bar library\nVersion: 2.1\n",
+    );
+    let discovery = discovery::DiscoveryOptions {
+        search_dirs: vec![dir.path().join("pc")],
+        ..discovery::DiscoveryOptions::default()
+    };
+    let outdir = dir.path().join("out");
+    let options = GenerateOptions {
+        index: true,
+        ..GenerateOptions::default()
+    };
+
+    generate_all_from_pkg_config(&discovery, &outdir, &options)?;
+    let index: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(outdir.join(INDEX_FILENAME))?)?;
+    assert_eq!(
+        index,
+        serde_json::json!({
+            "bar": { "version": "2.1", "file": "bar-2.cps" },
+            "foo": { "version": "1.0", "file": "foo.cps" }
+        })
+    );
+
+    let read_index = || -> Result<serde_json::Value> {
+        Ok(serde_json::from_str(&fs::read_to_string(
+            outdir.join(INDEX_FILENAME),
+        )?)?)
+    };
+    // a run that fails on the existing files leaves the index as it is
+    assert!(generate_all_from_pkg_config(&discovery, &outdir, &options).is_err());
+    assert_eq!(read_index()?, index);
+
+    // and a run that writes nothing doesn't write one
+    fs::remove_file(outdir.join(INDEX_FILENAME))?;
+    let options = GenerateOptions {
+        overwrite: Overwrite::Skip,
+        ..options
+    };
+    generate_all_from_pkg_config(&discovery, &outdir, &options)?;
+    assert!(!outdir.join(INDEX_FILENAME).exists());
+
+    // the files skipped because they exist are still indexed, and of two versions of a
+    // package only the first is
+    dir.write(
+        "pc/bar-3.pc",
+        "Name: bar\nDescription: Bar library\nVersion: 3.0\n",
+    );
+    generate_all_from_pkg_config(&discovery, &outdir, &options)?;
+    assert!(outdir.join("bar-3.cps").exists());
+    assert_eq!(read_index()?, index);
+
+    // an existing index is kept or replaced like the cps files, and a file is indexed once
+    fs::remove_file(outdir.join("foo.cps"))?;
+    fs::write(outdir.join(INDEX_FILENAME), "{}")?;
+    generate_all_from_pkg_config(&discovery, &outdir, &options)?;
+    assert_eq!(read_index()?, serde_json::json!({}));
+    let foo = [outdir.join("foo.cps"), outdir.join("foo.cps")];
+    write_index(&outdir, &foo, &GenerateOptions::default()).expect_err("the index exists");
+    write_index(
+        &outdir,
+        &foo,
+        &GenerateOptions {
+            overwrite: Overwrite::Force,
+            ..GenerateOptions::default()
+        },
+    )?;
+    assert_eq!(
+        read_index()?,
+        serde_json::json!({ "foo": { "version": "1.0", "file": "foo.cps" } })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generate_from_list() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    for name in ["foo", "bar", "baz"] {
+        dir.write_pc(format!("pc/{}.pc", name), name);
+    }
+    let list = dir.write("pc/list.txt", "# the curated set\nfoo.pc\nbar.pc\n");
+    let discovery = discovery::DiscoveryOptions {
+        from_list: Some(list),
+        ..discovery::DiscoveryOptions::default()
+    };
+    let outdir = dir.path().join("out");
+
+    generate_all_from_pkg_config(&discovery, &outdir, &GenerateOptions::default())?;
+    let mut written: Vec<_> = fs::read_dir(&outdir)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    written.sort();
+    assert_eq!(written, ["bar.cps", "foo.cps"]);
+    Ok(())
+}
+
+#[test]
+fn test_requires_map_matches_component_requires() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar >= 1.2, baz qux
+Cflags: -I/usr/include/foo
+    "#;
+
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(data)?,
+        &GenerateOptions::default(),
+    )?;
+    let required_keys: std::collections::HashSet<_> = package
+        .requires
+        .iter()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    let component_requires: std::collections::HashSet<_> = package
+        .components
+        .values()
+        .filter_map(|component| match component {
+            cps::MaybeComponent::Component(component) => component.fields(),
+            cps::MaybeComponent::Other(_) => None,
+        })
+        .flat_map(|fields| {
+            fields.requires.iter().flatten().cloned().chain(
+                fields
+                    .configurations
+                    .iter()
+                    .flat_map(|c| c.values())
+                    .flat_map(|c| c.requires.iter().flatten().cloned()),
+            )
+        })
+        .filter(|name| !name.starts_with(':'))
+        .collect();
+
+    assert_eq!(required_keys, component_requires);
+    assert_eq!(required_keys.len(), 3);
+    assert_eq!(
+        package.requires.as_ref().unwrap()["bar"].version.as_deref(),
+        Some("1.2")
+    );
+    assert_eq!(package.requires.as_ref().unwrap()["baz"].version, None);
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> Result<()> {
+    let package = convert(
+        pkg_config::PkgConfigFile::parse(&fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/selftest/eigen3.pc"),
+        )?)?,
+        &GenerateOptions::default(),
+    )?;
+    round_trip(&package, &GenerateOptions::default())?;
+
+    // A package that is written but doesn't read back as a valid package
+    let mut package = package;
+    if let Some(cps::MaybeComponent::Component(cps::Component::Interface(fields))) =
+        package.components.get_mut("Eigen3")
+    {
+        fields.location = Some("/usr/lib/libeigen3.so".to_string());
+    }
+    assert!(round_trip(&package, &GenerateOptions::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_generate_one() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = dir.write(
+        "pkgconfig/foo.pc",
+        "prefix=/opt/foo\nName: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I${prefix}/include\n",
+    );
+    dir.write(
+        "pkgconfig/bar.pc",
+        "Name: bar\nDescription: Bar library\nVersion: 2.0\n",
+    );
+    let cps = dir.path().join("out/renamed.cps");
+    fs::create_dir_all(dir.path().join("out"))?;
+
+    generate_one(&pc, &cps, &GenerateOptions::default())?;
+    assert_eq!(fs::read_dir(dir.path().join("out"))?.count(), 1);
+    let package = cps::load_cps(&cps)?;
+    assert_eq!(package.name, "foo");
+    assert_eq!(
+        package.components["foo"].fields().unwrap().includes,
+        Some(cps::LanguageStringList::any_language_map(vec![
+            "/opt/foo/include".to_string()
+        ]))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_convert_all() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc_files = vec![
+        dir.write(
+            "foo.pc",
+            "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/opt/foo/include\n",
+        ),
+        dir.write("broken.pc", "Description: Missing a name\nVersion: 1.0\n"),
+        dir.path().join("missing.pc"),
+    ];
+
+    let results = convert_all(&pc_files, &GenerateOptions::default());
+    let paths: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, pc_files);
+
+    let package = results[0].1.as_ref().expect("foo.pc converts");
+    package.validate()?;
+    assert_eq!(package.name, "foo");
+    assert_eq!(package.default_components, Some(vec!["foo".to_string()]));
+
+    let error = format!("{:#}", results[1].1.as_ref().unwrap_err());
+    assert!(error.contains("broken.pc"), "{}", error);
+    assert!(error.contains("`Name`"), "{}", error);
+    assert!(results[2].1.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_generate_all_skips_duplicate_packages() -> Result<()> {
+    let dir = crate::test_util::TempDir::new();
+    let pc = "Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I/opt/foo/include\n";
+    let pc_files = vec![
+        dir.write("lib/pkgconfig/foo.pc", pc),
+        dir.write("lib/x86_64-linux-gnu/pkgconfig/foo-copy.pc", pc),
+    ];
+    let outdir = dir.path().join("out");
+    fs::create_dir_all(&outdir)?;
+
+    let results = generate_all(&pc_files, &outdir, &GenerateOptions::default())?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, pc_files[0]);
+    assert_eq!(fs::read_dir(&outdir)?.count(), 1);
+
+    // another version with the same filename would be written to the same cps file
+    let pc_files = vec![
+        dir.write_pc("a/bar.pc", "bar"),
+        dir.write(
+            "b/bar.pc",
+            "Name: bar\nDescription: Bar library\nVersion: 2.0\n",
+        ),
+    ];
+    for overwrite in [Overwrite::Error, Overwrite::Force] {
+        let options = GenerateOptions {
+            overwrite,
+            ..GenerateOptions::default()
+        };
+        fs::remove_dir_all(&outdir)?;
+        fs::create_dir_all(&outdir)?;
+        let results = generate_all(&pc_files, &outdir, &options)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, pc_files[0]);
+        let package = cps::load_cps(&outdir.join("bar.cps"))?;
+        assert_eq!(package.version.as_deref(), Some("1.0"));
+    }
     Ok(())
 }
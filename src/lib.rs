@@ -1,4 +1,22 @@
+pub mod cmake;
+pub mod config;
 pub mod cps;
+pub mod diff;
+pub mod discovery;
+pub mod doctor;
+mod error;
+pub mod features;
+pub mod flags;
 pub mod generate_from_pkg_config;
 pub mod lib_search;
+pub mod license;
+pub mod meson;
 pub mod pkg_config;
+pub mod platform;
+mod progress;
+pub mod version;
+
+pub use error::Error;
+
+#[cfg(test)]
+mod test_util;
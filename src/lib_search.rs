@@ -1,46 +1,211 @@
-use anyhow::{anyhow, Result};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::OnceLock;
+use std::sync::{Mutex, OnceLock};
 
 use crate::pkg_config::PkgConfigFile;
+use crate::Error;
+
+/// The target triple `compiler` builds for, e.g. `x86_64-linux-gnu`, from `-dumpmachine`, or
+/// `None` if the compiler can't be run
+pub(crate) fn dump_machine(compiler: &str) -> Option<String> {
+    let output = Command::new(compiler).arg("-dumpmachine").output().ok()?;
+    let triple = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !triple.is_empty()).then_some(triple)
+}
+
+/// The target triple of the host compiler, `gcc` or else `clang`
+pub(crate) fn get_target_triple() -> Option<&'static str> {
+    static TARGET_TRIPLE: OnceLock<Option<String>> = OnceLock::new();
+    TARGET_TRIPLE
+        .get_or_init(|| ["gcc", "clang"].into_iter().find_map(dump_machine))
+        .as_deref()
+}
 
 fn get_multiarch_lib_path_iter() -> &'static [PathBuf] {
     static MULTIARCH_PATH: OnceLock<Vec<PathBuf>> = OnceLock::new();
     MULTIARCH_PATH.get_or_init(|| {
-        Command::new("gcc")
-            .arg("-dumpmachine")
-            .output()
-            .map(|o| String::from_utf8(o.stdout).unwrap_or_default())
-            .map_or(vec![], |arch| {
-                vec![PathBuf::from(format!("/usr/lib/{}", arch.trim()))]
+        get_target_triple().map_or(vec![], |arch| {
+            vec![PathBuf::from(format!("/usr/lib/{}", arch))]
+        })
+    })
+}
+
+/// Which library to choose when it is found in more than one search path
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOrder {
+    /// The first match wins, respecting the order of the `-L` flags like the linker does
+    #[default]
+    First,
+    /// The last match in the `-L` directories wins, the multiarch library directory is still
+    /// only a fallback
+    Last,
+}
+
+/// Options controlling how libraries named by `-l` flags are found
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Resolve shared libraries through the dynamic linker cache (`ldconfig -p`) instead of
+    /// the multiarch library directory
+    pub use_ldconfig: bool,
+    pub match_order: MatchOrder,
+    /// Directory the library search paths are under, e.g. the root of a cross-compilation
+    /// target, the dynamic linker cache of the host is not used with a sysroot
+    pub sysroot: Option<PathBuf>,
+    /// Extension of shared libraries, e.g. `so` for `libfoo.so`
+    pub dylib_extension: String,
+    /// Extension of static libraries, e.g. `a` for `libfoo.a`
+    pub archive_extension: String,
+    /// Extension of the link stubs found in place of a missing shared library, e.g. `tbd` for
+    /// the text-based stubs of the system libraries in the macOS SDKs
+    pub dylib_stub_extension: Option<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            use_ldconfig: false,
+            match_order: MatchOrder::default(),
+            sysroot: None,
+            dylib_extension: "so".to_string(),
+            archive_extension: "a".to_string(),
+            dylib_stub_extension: cfg!(target_os = "macos").then(|| "tbd".to_string()),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// The directories to find a library in: the search paths, followed by the multiarch
+    /// library directory if `multiarch` is set, all under the sysroot if there is one
+    pub(crate) fn library_dirs(&self, search_paths: &[PathBuf], multiarch: bool) -> Vec<PathBuf> {
+        let multiarch = if multiarch {
+            get_multiarch_lib_path_iter()
+        } else {
+            &[]
+        };
+        search_paths
+            .iter()
+            .chain(multiarch)
+            .map(|path| match &self.sysroot {
+                Some(sysroot) => sysroot.join(path.strip_prefix("/").unwrap_or(path)),
+                None => path.clone(),
             })
+            .collect()
+    }
+
+    /// The directories to fall back to when a library is in none of the search paths: the
+    /// multiarch library directory, under the sysroot if there is one
+    pub(crate) fn fallback_dirs(&self) -> Vec<PathBuf> {
+        self.library_dirs(&[], true)
+    }
+}
+
+/// Parse the listing printed by `ldconfig -p` into a map of library filename to path
+///
+/// When a filename is listed more than once (e.g. for several ABIs) the first entry wins.
+fn parse_ldconfig_cache(output: &str) -> HashMap<String, PathBuf> {
+    let mut cache = HashMap::new();
+    for line in output.lines() {
+        let Some((entry, path)) = line.split_once(" => ") else {
+            continue;
+        };
+        let Some(filename) = entry.split_whitespace().next() else {
+            continue;
+        };
+        cache
+            .entry(filename.to_string())
+            .or_insert_with(|| PathBuf::from(path.trim()));
+    }
+    cache
+}
+
+fn get_ldconfig_cache() -> &'static HashMap<String, PathBuf> {
+    static LDCONFIG_CACHE: OnceLock<HashMap<String, PathBuf>> = OnceLock::new();
+    LDCONFIG_CACHE.get_or_init(|| {
+        ["ldconfig", "/sbin/ldconfig"]
+            .iter()
+            .find_map(|ldconfig| Command::new(ldconfig).arg("-p").output().ok())
+            .map(|o| parse_ldconfig_cache(&String::from_utf8_lossy(&o.stdout)))
+            .unwrap_or_default()
     })
 }
 
-pub fn find_library(library: &str, extension: &str, search_paths: &[PathBuf]) -> Result<String> {
+fn find_in_ldconfig_cache(library: &str, cache: &HashMap<String, PathBuf>) -> Option<String> {
+    let filename = format!("lib{}.so", library);
+    cache
+        .get(&filename)
+        .and_then(|path| path.to_str())
+        .map(String::from)
+}
+
+pub fn find_library(
+    library: &str,
+    extension: &str,
+    search_paths: &[PathBuf],
+    match_order: MatchOrder,
+) -> Result<String, Error> {
+    let filename = format!("lib{}.{}", library, extension);
+    find_file(library, &filename, search_paths, match_order)
+}
+
+/// Find `filename` in the search paths, falling back to the multiarch library directory
+fn find_file(
+    library: &str,
+    filename: &str,
+    search_paths: &[PathBuf],
+    match_order: MatchOrder,
+) -> Result<String, Error> {
+    find_in_paths(
+        library,
+        filename,
+        search_paths,
+        get_multiarch_lib_path_iter(),
+        match_order,
+    )
+}
+
+/// Find the `filename` of `library` in the search paths, or else in the fallback paths
+///
+/// The `match_order` chooses among the search paths, the fallback paths are only tried in order
+/// when the file is in none of them.
+fn find_in_paths(
+    library: &str,
+    filename: &str,
+    search_paths: &[PathBuf],
+    fallback_paths: &[PathBuf],
+    match_order: MatchOrder,
+) -> Result<String, Error> {
     let filepaths: Vec<_> = search_paths
         .iter()
-        .chain(get_multiarch_lib_path_iter())
-        .map(|base| base.join(format!("lib{}.{}", library, extension)))
+        .chain(fallback_paths)
+        .map(|base| base.join(filename))
         .collect();
+    let (searched, fallback) = filepaths.split_at(search_paths.len());
 
-    let error = anyhow!(
-        "Could not find required library `{}` at paths: `{:?}`",
-        library,
-        &filepaths
-    );
-    Ok(filepaths
-        .into_iter()
-        .find(|path| path.exists())
-        .ok_or(error)?
+    let mut matches = searched.iter().filter(|path| path.exists());
+    let found = match match_order {
+        MatchOrder::First => matches.next(),
+        MatchOrder::Last => matches.next_back(),
+    }
+    .or_else(|| fallback.iter().find(|path| path.exists()));
+    let found = found.cloned().ok_or_else(|| Error::LibraryNotFound {
+        library: library.to_string(),
+        paths: filepaths.clone(),
+    })?;
+    found
         .into_os_string()
         .into_string()
-        .unwrap())
+        .map_err(|path| Error::NonUtf8Path {
+            library: library.to_string(),
+            path,
+        })
 }
 
-#[derive(Debug)]
+/// Where the library named by a `-l` flag was found
+///
+/// More kinds of locations may be added, so matches need a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum LibraryLocation {
     Archive(String),
     Dylib(String),
@@ -48,36 +213,558 @@ pub enum LibraryLocation {
 }
 
 impl LibraryLocation {
-    pub fn find(library: &str, search_paths: &[PathBuf]) -> Result<Self> {
-        let dylib = find_library(library, "so", search_paths);
-        let archive = find_library(library, "a", search_paths);
+    pub fn find(
+        library: &str,
+        search_paths: &[PathBuf],
+        options: &SearchOptions,
+    ) -> Result<Self, Error> {
+        let library_dirs = options.library_dirs(search_paths, false);
+        let fallback_dirs = options.fallback_dirs();
+
+        // `-l:libfoo.so.1` links exactly the named file
+        if let Some(filename) = library.strip_prefix(':') {
+            let location = find_in_paths(
+                library,
+                filename,
+                &library_dirs,
+                &fallback_dirs,
+                options.match_order,
+            )?;
+            return Ok(
+                if Path::new(filename)
+                    .extension()
+                    .is_some_and(|extension| *extension == *options.archive_extension)
+                {
+                    Self::Archive(location)
+                } else {
+                    Self::Dylib(location)
+                },
+            );
+        }
+
+        let dylib_filename = format!("lib{}.{}", library, options.dylib_extension);
+        let dylib = if options.use_ldconfig && options.sysroot.is_none() {
+            find_in_paths(
+                library,
+                &dylib_filename,
+                &library_dirs,
+                &[],
+                options.match_order,
+            )
+            .or_else(|error| find_in_ldconfig_cache(library, get_ldconfig_cache()).ok_or(error))
+        } else {
+            find_in_paths(
+                library,
+                &dylib_filename,
+                &library_dirs,
+                &fallback_dirs,
+                options.match_order,
+            )
+        };
+        // The stub is what gets linked, so it stands for the shared library
+        let dylib = match (dylib, &options.dylib_stub_extension) {
+            (Err(Error::LibraryNotFound { mut paths, .. }), Some(extension)) => {
+                let stub_filename = format!("lib{}.{}", library, extension);
+                find_in_paths(
+                    library,
+                    &stub_filename,
+                    &library_dirs,
+                    &fallback_dirs,
+                    options.match_order,
+                )
+                .map_err(|error| match error {
+                    Error::LibraryNotFound {
+                        library,
+                        paths: stub_paths,
+                    } => {
+                        paths.extend(stub_paths);
+                        Error::LibraryNotFound { library, paths }
+                    }
+                    error => error,
+                })
+            }
+            (dylib, _) => dylib,
+        };
+        let archive_filename = format!("lib{}.{}", library, options.archive_extension);
+        let archive = find_in_paths(
+            library,
+            &archive_filename,
+            &library_dirs,
+            &fallback_dirs,
+            options.match_order,
+        );
 
         match (dylib, archive) {
             (Ok(dylib), Err(_)) => Ok(Self::Dylib(dylib)),
             (Err(_), Ok(archive)) => Ok(Self::Archive(archive)),
             (Ok(dylib), Ok(archive)) => Ok(Self::Both { archive, dylib }),
-            (Err(dylib_error), Err(archive_error)) => {
-                Err(anyhow!("{}\n{}", dylib_error, archive_error))
+            (
+                Err(Error::LibraryNotFound { library, mut paths }),
+                Err(Error::LibraryNotFound {
+                    paths: archive_paths,
+                    ..
+                }),
+            ) => {
+                paths.extend(archive_paths);
+                Err(Error::LibraryNotFound { library, paths })
             }
+            (Err(Error::LibraryNotFound { .. }), Err(error)) | (Err(error), Err(_)) => Err(error),
         }
     }
 }
 
-pub fn find_locations(pkg_config: &PkgConfigFile) -> Result<HashMap<String, LibraryLocation>> {
+/// The component name of a library named by a `-l` flag, e.g. `foo` for both `-lfoo` and the
+/// exact filename form `-l:libfoo.so.1`
+pub fn library_name(library: &str) -> &str {
+    let Some(filename) = library.strip_prefix(':') else {
+        return library;
+    };
+    let name = filename.strip_prefix("lib").unwrap_or(filename);
+    let end = [".so", ".a", ".dylib"]
+        .iter()
+        .filter_map(|extension| name.find(extension))
+        .min()
+        .unwrap_or(name.len());
+    &name[..end]
+}
+
+/// The SONAME recorded in the dynamic section of a shared library, or `None` if `readelf` is
+/// not available or the file is not ELF
+#[cfg(feature = "soname")]
+pub fn soname(location: &str) -> Option<String> {
+    let output = Command::new("readelf")
+        .arg("-d")
+        .arg(location)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_readelf_soname(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reading the SONAME is disabled without the `soname` feature
+#[cfg(not(feature = "soname"))]
+pub fn soname(_location: &str) -> Option<String> {
+    None
+}
+
+/// The name of a shared library by its SONAME, e.g. `foo-2` for a library with the SONAME
+/// `libfoo-2.so.1`, or `None` if the SONAME can't be read, see [`soname`]
+pub fn soname_library_name(location: &str) -> Option<String> {
+    soname(location).map(|soname| library_name(&format!(":{}", soname)).to_string())
+}
+
+/// The SONAME in the output of `readelf -d`, from a line like
+/// `0x000000000000000e (SONAME)  Library soname: [libfoo.so.2]`
+#[cfg(feature = "soname")]
+fn parse_readelf_soname(output: &str) -> Option<String> {
+    let line = output.lines().find(|line| line.contains("(SONAME)"))?;
+    let start = line.find('[')? + 1;
+    let end = start + line[start..].find(']')?;
+    Some(line[start..end].to_string())
+}
+
+/// The major version in a shared library filename, e.g. `2` for `libfoo.so.2.3.1`
+fn filename_major_version(filename: &str) -> Option<String> {
+    let (_, version) = filename.split_once(".so.")?;
+    let major = version.split('.').next()?;
+    (!major.is_empty() && major.bytes().all(|b| b.is_ascii_digit())).then(|| major.to_string())
+}
+
+/// The ABI major version of a shared library, e.g. `2` for a library with the SONAME
+/// `libfoo.so.2`, or else for a location that is or links to `libfoo.so.2.3.1`
+pub fn abi_major_version(location: &str) -> Option<String> {
+    if let Some(major) = soname(location).and_then(|soname| filename_major_version(&soname)) {
+        return Some(major);
+    }
+    let path = std::fs::canonicalize(location).unwrap_or_else(|_| PathBuf::from(location));
+    filename_major_version(path.file_name()?.to_str()?)
+}
+
+/// Finds where a library named by a `-l` flag is on disk
+pub trait LibraryResolver {
+    fn find(&self, library: &str, search_paths: &[PathBuf]) -> Result<LibraryLocation, Error>;
+}
+
+impl LibraryResolver for SearchOptions {
+    fn find(&self, library: &str, search_paths: &[PathBuf]) -> Result<LibraryLocation, Error> {
+        LibraryLocation::find(library, search_paths, self)
+    }
+}
+
+type LookupKey = (String, Vec<PathBuf>);
+
+/// Memoizes the lookups of another resolver by library name and search paths, so a sweep
+/// over many packages probes the filesystem for each library only once
+pub struct CachedResolver<R> {
+    resolver: R,
+    cache: Mutex<HashMap<LookupKey, Result<LibraryLocation, Error>>>,
+}
+
+impl<R: LibraryResolver> CachedResolver<R> {
+    pub fn new(resolver: R) -> Self {
+        Self {
+            resolver,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<R: LibraryResolver> LibraryResolver for CachedResolver<R> {
+    fn find(&self, library: &str, search_paths: &[PathBuf]) -> Result<LibraryLocation, Error> {
+        let key = (library.to_string(), search_paths.to_vec());
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(key)
+            .or_insert_with(|| self.resolver.find(library, search_paths))
+            .clone()
+    }
+}
+
+/// The libraries of `Libs`, see [`find_locations`]
+#[derive(Debug, Default)]
+pub struct FoundLibraries {
+    /// The location of each library found, by component name
+    pub locations: HashMap<String, LibraryLocation>,
+    /// The libraries that weren't found, as named by their `-l` flag
+    pub unresolved: Vec<String>,
+}
+
+/// Find the libraries of `Libs`, leaving those that can't be found to the linker
+///
+/// Fails only when none of the libraries is found, as the package is then most likely not
+/// installed rather than linking to some system library.
+pub fn find_locations(
+    pkg_config: &PkgConfigFile,
+    resolver: &dyn LibraryResolver,
+) -> Result<FoundLibraries, Error> {
     let search_paths = pkg_config
         .link_locations
         .iter()
         .map(PathBuf::from)
         .collect::<Vec<_>>();
 
-    Ok(pkg_config
-        .link_libraries
+    let mut found = FoundLibraries::default();
+    let mut first_error = None;
+    for name in &pkg_config.link_libraries {
+        match resolver.find(name, &search_paths) {
+            Ok(location) => {
+                found
+                    .locations
+                    .insert(library_name(name).to_string(), location);
+            }
+            Err(error @ Error::LibraryNotFound { .. }) => {
+                first_error.get_or_insert(error);
+                found.unresolved.push(name.clone());
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    match first_error {
+        Some(error) if found.locations.is_empty() => Err(error),
+        _ => Ok(found),
+    }
+}
+
+/// Find the libraries of `Libs.private`, leaving out any that can't be found
+///
+/// Private libraries are only needed for static linking and are often system libraries like
+/// `m`, so not finding one is not an error.
+pub fn find_private_locations(
+    pkg_config: &PkgConfigFile,
+    resolver: &dyn LibraryResolver,
+) -> HashMap<String, LibraryLocation> {
+    let search_paths = pkg_config
+        .link_locations_private
+        .iter()
+        .chain(&pkg_config.link_locations)
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+
+    pkg_config
+        .link_libraries_private
         .iter()
-        .map(|name| -> Result<(String, LibraryLocation)> {
-            let location = LibraryLocation::find(name, &search_paths)?;
-            Ok((name.clone(), location))
+        .filter(|&name| !pkg_config.link_libraries.contains(name))
+        .filter_map(|name| {
+            let location = resolver.find(name, &search_paths).ok()?;
+            Some((library_name(name).to_string(), location))
         })
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .collect())
+        .collect()
+}
+
+#[test]
+fn test_ldconfig_cache() {
+    let output = r#"4 libs found in cache `/etc/ld.so.cache'
+	libz.so.1 (libc6,x86-64) => /lib/x86_64-linux-gnu/libz.so.1
+	libz.so (libc6,x86-64) => /lib/x86_64-linux-gnu/libz.so
+	libz.so (libc6) => /lib/i386-linux-gnu/libz.so
+	libfoo.so (libc6,x86-64) => /opt/foo/lib/libfoo.so
+Cache generated by: ldconfig (GNU libc) stable release version 2.35
+"#;
+    let cache = parse_ldconfig_cache(output);
+    assert_eq!(cache.len(), 3);
+
+    assert_eq!(
+        find_in_ldconfig_cache("z", &cache).as_deref(),
+        Some("/lib/x86_64-linux-gnu/libz.so")
+    );
+    assert_eq!(
+        find_in_ldconfig_cache("foo", &cache).as_deref(),
+        Some("/opt/foo/lib/libfoo.so")
+    );
+    assert!(find_in_ldconfig_cache("bar", &cache).is_none());
+}
+
+#[test]
+fn test_exact_filename() -> Result<(), Error> {
+    let dir = crate::test_util::TempDir::new();
+    let exact = dir.write("lib/libcps-deps-exact.so.1", "");
+    dir.write("lib/libcps-deps-exact.so", "");
+    let search_paths = [dir.path().join("lib")];
+
+    assert_eq!(
+        LibraryLocation::find(
+            ":libcps-deps-exact.so.1",
+            &search_paths,
+            &SearchOptions::default()
+        )?,
+        LibraryLocation::Dylib(exact.to_str().unwrap().to_string())
+    );
+    assert_eq!(library_name(":libcps-deps-exact.so.1"), "cps-deps-exact");
+    assert_eq!(library_name(":libfoo.a"), "foo");
+    assert_eq!(library_name("foo"), "foo");
+    Ok(())
+}
+
+#[test]
+fn test_sysroot_and_extensions() -> Result<(), Error> {
+    let dir = crate::test_util::TempDir::new();
+    let dylib = dir.write("sysroot/usr/lib/libcps-deps-sysroot.dylib", "");
+    let options = SearchOptions {
+        sysroot: Some(dir.path().join("sysroot")),
+        dylib_extension: "dylib".to_string(),
+        ..SearchOptions::default()
+    };
+
+    assert_eq!(
+        LibraryLocation::find("cps-deps-sysroot", &[PathBuf::from("/usr/lib")], &options)?,
+        LibraryLocation::Dylib(dylib.to_str().unwrap().to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dylib_stub() -> Result<(), Error> {
+    let dir = crate::test_util::TempDir::new();
+    let stub = dir.write("usr/lib/libcps-deps-stub.tbd", "");
+    let search_paths = [dir.path().join("usr/lib")];
+    let options = SearchOptions {
+        dylib_extension: "dylib".to_string(),
+        dylib_stub_extension: Some("tbd".to_string()),
+        ..SearchOptions::default()
+    };
+
+    assert_eq!(
+        LibraryLocation::find("cps-deps-stub", &search_paths, &options)?,
+        LibraryLocation::Dylib(stub.to_str().unwrap().to_string())
+    );
+
+    // A real dylib is preferred to the stub
+    let dylib = dir.write("usr/lib/libcps-deps-stub.dylib", "");
+    assert_eq!(
+        LibraryLocation::find("cps-deps-stub", &search_paths, &options)?,
+        LibraryLocation::Dylib(dylib.to_str().unwrap().to_string())
+    );
+
+    match LibraryLocation::find("cps-deps-missing", &search_paths, &options) {
+        Err(Error::LibraryNotFound { paths, .. }) => {
+            assert!(paths.contains(&dir.path().join("usr/lib/libcps-deps-missing.tbd")));
+        }
+        result => panic!("expected LibraryNotFound, got {:?}", result),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_match_order() -> Result<(), Error> {
+    let dir = crate::test_util::TempDir::new();
+    let local = dir.write("local/libcps-deps-dup.so", "");
+    let system = dir.write("system/libcps-deps-dup.so", "");
+    let search_paths = [dir.path().join("local"), dir.path().join("system")];
+
+    assert_eq!(
+        find_library("cps-deps-dup", "so", &search_paths, MatchOrder::First)?,
+        local.to_str().unwrap()
+    );
+    assert_eq!(
+        find_library("cps-deps-dup", "so", &search_paths, MatchOrder::Last)?,
+        system.to_str().unwrap()
+    );
+
+    // The last match is only chosen among the search paths, the fallback is tried after them
+    let fallback = [dir.path().join("fallback")];
+    let fallback_library = dir.write("fallback/libcps-deps-dup.so", "");
+    assert_eq!(
+        find_in_paths(
+            "cps-deps-dup",
+            "libcps-deps-dup.so",
+            &search_paths,
+            &fallback,
+            MatchOrder::Last
+        )?,
+        system.to_str().unwrap()
+    );
+    assert_eq!(
+        find_in_paths(
+            "cps-deps-dup",
+            "libcps-deps-dup.so",
+            &[dir.path().join("empty")],
+            &fallback,
+            MatchOrder::Last
+        )?,
+        fallback_library.to_str().unwrap()
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_non_utf8_library_path() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = crate::test_util::TempDir::new();
+    let search_path = dir.path().join(OsStr::from_bytes(b"lib\xff"));
+    std::fs::create_dir_all(&search_path).unwrap();
+    std::fs::write(search_path.join("libcps-deps-non-utf8.so"), "").unwrap();
+
+    let error = find_library("cps-deps-non-utf8", "so", &[search_path], MatchOrder::First)
+        .expect_err("a non UTF-8 path can't be returned as a String");
+    assert!(
+        matches!(&error, Error::NonUtf8Path { library, .. } if library == "cps-deps-non-utf8"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_abi_major_version() {
+    let dir = crate::test_util::TempDir::new();
+    let real = dir.write("libfoo.so.2.3.1", "");
+    assert_eq!(
+        abi_major_version(real.to_str().unwrap()).as_deref(),
+        Some("2")
+    );
+    #[cfg(unix)]
+    {
+        let link = dir.path().join("libfoo.so");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        assert_eq!(
+            abi_major_version(link.to_str().unwrap()).as_deref(),
+            Some("2")
+        );
+    }
+    assert_eq!(abi_major_version("/nonexistent/libbar.so"), None);
+    assert_eq!(abi_major_version("/nonexistent/libbar.a"), None);
+}
+
+#[cfg(feature = "soname")]
+#[test]
+fn test_soname() {
+    assert_eq!(
+        parse_readelf_soname(
+            " 0x000000000000000e (SONAME)             Library soname: [libfoo.so.2]\n"
+        )
+        .as_deref(),
+        Some("libfoo.so.2")
+    );
+    assert_eq!(
+        parse_readelf_soname("There is no dynamic section in this file.\n"),
+        None
+    );
+    if Command::new("readelf").arg("--version").output().is_err() {
+        return;
+    }
+
+    // The fixture is named without a version, so the version can only come from its SONAME
+    let dir = crate::test_util::TempDir::new();
+    let fixture = dir.path().join("libcps-fixture.so");
+    std::fs::write(&fixture, include_bytes!("../testdata/libcps-fixture.so")).unwrap();
+    let fixture = fixture.to_str().unwrap();
+    assert_eq!(soname(fixture).as_deref(), Some("libcps-fixture.so.3"));
+    assert_eq!(abi_major_version(fixture).as_deref(), Some("3"));
+
+    let not_elf = dir.write("libnotelf.so.4", "");
+    assert_eq!(soname(not_elf.to_str().unwrap()), None);
+    assert_eq!(
+        abi_major_version(not_elf.to_str().unwrap()).as_deref(),
+        Some("4")
+    );
+}
+
+#[test]
+fn test_cached_resolver() -> Result<(), Error> {
+    use std::cell::Cell;
+
+    #[derive(Default)]
+    struct CountingResolver {
+        lookups: Cell<usize>,
+    }
+
+    impl LibraryResolver for &CountingResolver {
+        fn find(&self, library: &str, search_paths: &[PathBuf]) -> Result<LibraryLocation, Error> {
+            self.lookups.set(self.lookups.get() + 1);
+            match search_paths {
+                [] => Err(Error::LibraryNotFound {
+                    library: library.to_string(),
+                    paths: vec![],
+                }),
+                [path, ..] => Ok(LibraryLocation::Dylib(format!(
+                    "{}/lib{}.so",
+                    path.display(),
+                    library
+                ))),
+            }
+        }
+    }
+
+    let counting = CountingResolver::default();
+    let resolver = CachedResolver::new(&counting);
+    let search_paths = [PathBuf::from("/opt/lib")];
+
+    let first = resolver.find("foo", &search_paths)?;
+    assert_eq!(
+        first,
+        LibraryLocation::Dylib("/opt/lib/libfoo.so".to_string())
+    );
+    assert_eq!(resolver.find("foo", &search_paths)?, first);
+    assert_eq!(counting.lookups.get(), 1);
+
+    resolver.find("foo", &[PathBuf::from("/usr/lib")])?;
+    assert_eq!(counting.lookups.get(), 2);
+
+    // failed lookups are cached too
+    assert!(resolver.find("foo", &[]).is_err());
+    assert!(matches!(
+        resolver.find("foo", &[]),
+        Err(Error::LibraryNotFound { library, .. }) if library == "foo"
+    ));
+    assert_eq!(counting.lookups.get(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_library_not_found() {
+    let dir = crate::test_util::TempDir::new();
+    let search_paths = [dir.path().to_path_buf()];
+
+    match LibraryLocation::find("cps-deps-missing", &search_paths, &SearchOptions::default()) {
+        Err(Error::LibraryNotFound { library, paths }) => {
+            assert_eq!(library, "cps-deps-missing");
+            assert!(paths.contains(&dir.path().join("libcps-deps-missing.so")));
+            assert!(paths.contains(&dir.path().join("libcps-deps-missing.a")));
+        }
+        result => panic!("expected LibraryNotFound, got {:?}", result),
+    }
 }
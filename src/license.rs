@@ -0,0 +1,72 @@
+/// Spellings of licenses common in pc files and their SPDX identifiers, matched ignoring case,
+/// spaces, dashes and underscores
+const SPDX_IDENTIFIERS: &[(&str, &str)] = &[
+    ("apache2", "Apache-2.0"),
+    ("apachelicense2", "Apache-2.0"),
+    ("asl2", "Apache-2.0"),
+    ("bsd", "BSD-3-Clause"),
+    ("bsd2", "BSD-2-Clause"),
+    ("bsd2clause", "BSD-2-Clause"),
+    ("bsd3", "BSD-3-Clause"),
+    ("bsd3clause", "BSD-3-Clause"),
+    ("boost", "BSL-1.0"),
+    ("bsl1", "BSL-1.0"),
+    ("gpl2", "GPL-2.0-only"),
+    ("gplv2", "GPL-2.0-only"),
+    ("gpl2+", "GPL-2.0-or-later"),
+    ("gpl2only", "GPL-2.0-only"),
+    ("gpl2orlater", "GPL-2.0-or-later"),
+    ("gplv2+", "GPL-2.0-or-later"),
+    ("gpl3", "GPL-3.0-only"),
+    ("gplv3", "GPL-3.0-only"),
+    ("gpl3+", "GPL-3.0-or-later"),
+    ("gpl3only", "GPL-3.0-only"),
+    ("gpl3orlater", "GPL-3.0-or-later"),
+    ("gplv3+", "GPL-3.0-or-later"),
+    ("isc", "ISC"),
+    ("lgpl2.1", "LGPL-2.1-only"),
+    ("lgplv2.1", "LGPL-2.1-only"),
+    ("lgpl2.1+", "LGPL-2.1-or-later"),
+    ("lgpl2.1only", "LGPL-2.1-only"),
+    ("lgpl2.1orlater", "LGPL-2.1-or-later"),
+    ("lgplv2.1+", "LGPL-2.1-or-later"),
+    ("lgpl3", "LGPL-3.0-only"),
+    ("lgplv3", "LGPL-3.0-only"),
+    ("lgpl3+", "LGPL-3.0-or-later"),
+    ("lgpl3only", "LGPL-3.0-only"),
+    ("lgpl3orlater", "LGPL-3.0-or-later"),
+    ("lgplv3+", "LGPL-3.0-or-later"),
+    ("mit", "MIT"),
+    ("mpl2", "MPL-2.0"),
+    ("zlib", "Zlib"),
+];
+
+/// The SPDX identifier of a license as commonly spelled in pc files, e.g. `Apache-2.0` for
+/// `Apache 2.0`, or `None` if the spelling is not known
+pub fn to_spdx(license: &str) -> Option<&'static str> {
+    let key: String = license
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect();
+    // The versions may be spelled with a trailing `.0`, e.g. `GPL-3.0` for `GPLv3`
+    let short_key = key.replace(".0", "");
+    SPDX_IDENTIFIERS
+        .iter()
+        .find(|(spelling, _)| *spelling == key || *spelling == short_key)
+        .map(|(_, identifier)| *identifier)
+}
+
+#[test]
+fn test_to_spdx() {
+    assert_eq!(to_spdx("Apache 2.0"), Some("Apache-2.0"));
+    assert_eq!(to_spdx("Apache-2.0"), Some("Apache-2.0"));
+    assert_eq!(to_spdx("BSD"), Some("BSD-3-Clause"));
+    assert_eq!(to_spdx("GPLv3"), Some("GPL-3.0-only"));
+    assert_eq!(to_spdx("GPL-3.0+"), Some("GPL-3.0-or-later"));
+    assert_eq!(to_spdx("LGPL-2.1"), Some("LGPL-2.1-only"));
+    assert_eq!(to_spdx("GPL-2.0-or-later"), Some("GPL-2.0-or-later"));
+    assert_eq!(to_spdx("mit"), Some("MIT"));
+    assert_eq!(to_spdx("BSL-1.0"), Some("BSL-1.0"));
+    assert_eq!(to_spdx("Some Custom License"), None);
+}
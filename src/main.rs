@@ -1,7 +1,18 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use cps_deps::cmake::export_cmake;
+use cps_deps::config::{Config, ConfigBuilder};
 use cps_deps::cps::parse_and_print_cps;
-use cps_deps::generate_from_pkg_config::{generate_all_from_pkg_config, generate_from_pkg_config};
+use cps_deps::diff::print_diff;
+use cps_deps::discovery::{find_pc_file, print_package_list};
+use cps_deps::doctor::print_doctor;
+use cps_deps::flags::print_flags;
+use cps_deps::generate_from_pkg_config::{
+    generate_all_from_pkg_config, generate_one, selftest, Indent, Overwrite,
+};
+use cps_deps::lib_search::MatchOrder;
+use cps_deps::meson::print_meson;
+use cps_deps::pkg_config::print_pkg_config_info;
 use std::path::PathBuf;
 
 /// Common Package Specification (CPS) deps
@@ -12,12 +23,182 @@ struct Args {
     command: Commands,
 }
 
+/// Options controlling where pc files are discovered
+#[derive(clap::Args, Debug)]
+struct DiscoveryArgs {
+    /// Directory to search for pc files, replaces the default search directories
+    #[arg(long = "search-dir", value_name = "DIR")]
+    search_dirs: Vec<PathBuf>,
+    /// Also discover pc files that are symlinks or in symlinked directories, skipping those
+    /// resolving to the same file
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Only use the pc files whose file stem or package name matches this glob, can be repeated
+    #[arg(long, value_name = "NAME")]
+    only: Vec<String>,
+    /// Skip the pc files whose file stem or package name matches this glob, can be repeated
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+    /// Use the pc files listed in this file, one per line, instead of searching for them
+    #[arg(long, value_name = "FILE")]
+    from_list: Option<PathBuf>,
+}
+
+impl DiscoveryArgs {
+    fn configure(&self, builder: ConfigBuilder) -> ConfigBuilder {
+        let builder = self
+            .search_dirs
+            .iter()
+            .fold(builder, |builder, dir| builder.search_dir(dir));
+        let builder = self
+            .only
+            .iter()
+            .fold(builder, |builder, pattern| builder.only(pattern));
+        let builder = self
+            .exclude
+            .iter()
+            .fold(builder, |builder, pattern| builder.exclude(pattern))
+            .follow_symlinks(self.follow_symlinks);
+        match &self.from_list {
+            Some(list) => builder.from_list(list),
+            None => builder,
+        }
+    }
+}
+
+/// Options shared by the generate commands
+#[derive(clap::Args, Debug)]
+struct GenerateArgs {
+    /// Include the nonstandard `Requires.internal` dependencies in the generated requires
+    #[arg(long)]
+    include_requires_internal: bool,
+    /// Print the pkg-config variable expansion passes and final variables to stderr
+    #[arg(long)]
+    trace: bool,
+    /// Fail on references to undefined pkg-config variables instead of leaving them in place
+    #[arg(long)]
+    strict: bool,
+    /// Override a variable defined in the pc files, e.g. `prefix=/opt/foo`, can be repeated
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_variable)]
+    define_variable: Vec<(String, String)>,
+    /// Define `prefix` as two directories above the `pkgconfig` directory of each pc file,
+    /// for installs moved after they were built
+    #[arg(long)]
+    define_prefix: bool,
+    /// Fill in the platform of the generated packages from the host compiler and C runtime
+    #[arg(long)]
+    platform: bool,
+    /// Resolve symlinks in the include and library paths that exist
+    #[arg(long)]
+    canonicalize: bool,
+    /// Replace common license spellings like `Apache 2.0` with their SPDX identifier, e.g.
+    /// `Apache-2.0`, warning about the licenses that have none
+    #[arg(long)]
+    spdx_license: bool,
+    /// Name the default component after the package, e.g. `Foo_Bar` for `Name: Foo Bar`,
+    /// instead of after its first library
+    #[arg(long)]
+    component_from_name: bool,
+    /// Make the shared libraries found in a `modules` or `plugins` directory `module`
+    /// components, which are loaded at runtime instead of linked
+    #[arg(long)]
+    plugin_modules: bool,
+    /// Write minified JSON instead of pretty-printing it
+    #[arg(long)]
+    compact: bool,
+    /// Indent the pretty-printed JSON by this many spaces, or `tab`
+    #[arg(long, value_name = "N|tab", default_value = "2")]
+    indent: Indent,
+    /// Skip the cps files that already exist instead of failing
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+    /// Overwrite the cps files that already exist instead of failing
+    #[arg(long)]
+    force: bool,
+    /// Find the libraries under this directory, e.g. the root of a cross-compilation target
+    #[arg(long, value_name = "DIR")]
+    sysroot: Option<PathBuf>,
+    /// Write an index.json mapping each generated package to its version and cps file
+    #[arg(long)]
+    index: bool,
+    /// Show the progress of the conversion when stderr is a terminal
+    #[arg(long)]
+    progress: bool,
+    /// Don't show progress or report skipped files
+    #[arg(long)]
+    quiet: bool,
+    /// Resolve shared libraries through the dynamic linker cache (`ldconfig -p`)
+    #[arg(long)]
+    use_ldconfig: bool,
+    /// When a library is in several search paths use the first one (default)
+    #[arg(long, overrides_with = "last_match_wins")]
+    first_match_wins: bool,
+    /// When a library is in several search paths use the last one
+    #[arg(long, overrides_with = "first_match_wins")]
+    last_match_wins: bool,
+}
+
+/// Parse a `name=value` variable definition
+fn parse_variable(definition: &str) -> Result<(String, String), String> {
+    match definition.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, found `{}`", definition)),
+    }
+}
+
+impl GenerateArgs {
+    fn configure(&self, builder: ConfigBuilder) -> ConfigBuilder {
+        let builder = self
+            .define_variable
+            .iter()
+            .fold(builder, |builder, (name, value)| {
+                builder.define_variable(name, value)
+            });
+        let builder = builder
+            .include_requires_internal(self.include_requires_internal)
+            .trace(self.trace)
+            .strict(self.strict)
+            .define_prefix(self.define_prefix)
+            .platform(self.platform)
+            .canonicalize(self.canonicalize)
+            .spdx_license(self.spdx_license)
+            .name_default_component_after_package(self.component_from_name)
+            .plugin_modules(self.plugin_modules)
+            .compact(self.compact)
+            .indent(self.indent)
+            .progress(self.progress)
+            .index(self.index)
+            .quiet(self.quiet)
+            .overwrite(if self.force {
+                Overwrite::Force
+            } else if self.no_clobber {
+                Overwrite::Skip
+            } else {
+                Overwrite::Error
+            })
+            .use_ldconfig(self.use_ldconfig)
+            // The flag given last wins, and the first match without either
+            .match_order(match (self.first_match_wins, self.last_match_wins) {
+                (false, true) => MatchOrder::Last,
+                _ => MatchOrder::First,
+            });
+        match &self.sysroot {
+            Some(sysroot) => builder.sysroot(sysroot),
+            None => builder,
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Generate cps files from pkg-config files found on your system
     GenerateAll {
         #[arg(value_name = "OUTDIR")]
         outdir: PathBuf,
+        #[command(flatten)]
+        discovery: DiscoveryArgs,
+        #[command(flatten)]
+        options: GenerateArgs,
     },
     /// Generate a cps file from a pkg config file
     Generate {
@@ -25,11 +206,116 @@ enum Commands {
         pc: PathBuf,
         #[arg(value_name = "CPS_FILE")]
         cps: PathBuf,
+        /// Print how the pc file is converted to stderr: the type of each component and why,
+        /// where the libraries were found, which flags became compile features and which
+        /// requires were kept
+        #[arg(long)]
+        explain: bool,
+        #[command(flatten)]
+        options: GenerateArgs,
+    },
+    /// Generate a cps file from the pc file of an installed package, found like pkg-config does
+    FromName {
+        #[arg(value_name = "PKG_NAME")]
+        name: String,
+        #[arg(value_name = "CPS_FILE")]
+        cps: PathBuf,
+        #[command(flatten)]
+        discovery: DiscoveryArgs,
+        #[command(flatten)]
+        options: GenerateArgs,
+    },
+    /// Check that each pc file found converts to a CPS package that reads back as written
+    Selftest {
+        #[command(flatten)]
+        discovery: DiscoveryArgs,
+        #[command(flatten)]
+        options: GenerateArgs,
+    },
+    /// List the pc files found on your system with their name and version
+    List {
+        #[command(flatten)]
+        discovery: DiscoveryArgs,
+        /// Print the list as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+    /// Parse a pc file and print how it was interpreted as JSON
+    Info {
+        /// The pc file to parse, or `-` to read it from stdin
+        #[arg(value_name = "PC_FILE")]
+        pc: PathBuf,
+    },
+    /// Check that a cps file matches what would be generated from a pc file
+    Diff {
+        #[arg(value_name = "PC_FILE")]
+        pc: PathBuf,
+        #[arg(value_name = "CPS_FILE")]
+        cps: PathBuf,
+        #[command(flatten)]
+        options: GenerateArgs,
+    },
+    /// Report the compilers, search paths and environment variables the conversion uses
+    Doctor {
+        #[command(flatten)]
+        discovery: DiscoveryArgs,
+        /// Find the libraries under this directory, e.g. the root of a cross-compilation target
+        #[arg(long, value_name = "DIR")]
+        sysroot: Option<PathBuf>,
     },
     /// Parse a CPS file and display the result
     ParseCps {
         #[arg(value_name = "FILE")]
         filepath: PathBuf,
+        /// Print the package as JSON
+        #[arg(long)]
+        json: bool,
+        /// Fail on components of a type that is not part of CPS
+        #[arg(long)]
+        strict: bool,
+        /// Fail on component locations that don't exist, under the install prefix found from
+        /// `cps_path`
+        #[arg(long)]
+        check_files: bool,
+    },
+    /// Write a CMake package config file importing the components of a CPS file as targets
+    ExportCmake {
+        #[arg(value_name = "CPS_FILE")]
+        cps: PathBuf,
+        #[arg(value_name = "OUTDIR")]
+        outdir: PathBuf,
+        /// Install prefix substituted for `@prefix@`, found from `cps_path` by default
+        #[arg(long, value_name = "DIR")]
+        prefix: Option<PathBuf>,
+    },
+    /// Print a Meson dependency declaration of the default components of a CPS file
+    ExportMeson {
+        #[arg(value_name = "CPS_FILE")]
+        cps: PathBuf,
+        /// Install prefix substituted for `@prefix@`, found from `cps_path` by default
+        #[arg(long, value_name = "DIR")]
+        prefix: Option<PathBuf>,
+    },
+    /// Print the compiler and linker flags of a CPS file, like pkg-config
+    Flags {
+        #[arg(value_name = "CPS_FILE")]
+        filepath: PathBuf,
+        /// Print the compiler flags
+        #[arg(long)]
+        cflags: bool,
+        /// Print the linker flags
+        #[arg(long)]
+        libs: bool,
+        /// Components to print flags for, defaults to the package's default components
+        #[arg(long = "component", value_name = "NAME")]
+        components: Vec<String>,
+        /// Configuration to print flags for, e.g. `debug`, whose attributes are added to those
+        /// of the components that have it
+        #[arg(long = "config", value_name = "NAME")]
+        configuration: Option<String>,
+        /// Directories to load required packages from, defaults to `CPS_PATH`
+        #[arg(long = "cps-path", value_name = "DIR")]
+        search_paths: Vec<PathBuf>,
     },
 }
 
@@ -37,8 +323,94 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     match &args.command {
-        Commands::GenerateAll { outdir } => generate_all_from_pkg_config(outdir),
-        Commands::Generate { pc, cps } => generate_from_pkg_config(pc, cps),
-        Commands::ParseCps { filepath } => parse_and_print_cps(filepath),
+        Commands::GenerateAll {
+            outdir,
+            discovery,
+            options,
+        } => {
+            let config = options
+                .configure(discovery.configure(Config::builder()))
+                .build();
+            generate_all_from_pkg_config(&config.discovery, outdir, &config.generate)
+        }
+        Commands::Generate {
+            pc,
+            cps,
+            explain,
+            options,
+        } => {
+            let config = options
+                .configure(Config::builder())
+                .explain(*explain)
+                .build();
+            generate_one(pc, cps, &config.generate)
+        }
+        Commands::FromName {
+            name,
+            cps,
+            discovery,
+            options,
+        } => {
+            let config = options
+                .configure(discovery.configure(Config::builder()))
+                .build();
+            let pc = find_pc_file(name, &config.discovery)?;
+            generate_one(&pc, cps, &config.generate)
+        }
+        Commands::Selftest { discovery, options } => {
+            let config = options
+                .configure(discovery.configure(Config::builder()))
+                .build();
+            selftest(&config.discovery, &config.generate)
+        }
+        Commands::List { discovery, json } => {
+            let config = discovery.configure(Config::builder()).build();
+            print_package_list(&config.discovery, *json)
+        }
+        Commands::Info { pc } => print_pkg_config_info(pc),
+        Commands::Diff { pc, cps, options } => {
+            let config = options.configure(Config::builder()).build();
+            print_diff(pc, cps, &config.generate)
+        }
+        Commands::Doctor { discovery, sysroot } => {
+            let builder = discovery.configure(Config::builder());
+            let config = match sysroot {
+                Some(sysroot) => builder.sysroot(sysroot),
+                None => builder,
+            }
+            .build();
+            print_doctor(&config)
+        }
+        Commands::ParseCps {
+            filepath,
+            json,
+            strict,
+            check_files,
+        } => parse_and_print_cps(filepath, *json, *strict, *check_files),
+        Commands::ExportCmake {
+            cps,
+            outdir,
+            prefix,
+        } => {
+            let path = export_cmake(cps, outdir, prefix.as_deref())?;
+            println!("{}", path.display());
+            Ok(())
+        }
+        Commands::ExportMeson { cps, prefix } => print_meson(cps, prefix.as_deref()),
+        Commands::Flags {
+            filepath,
+            cflags,
+            libs,
+            components,
+            configuration,
+            search_paths,
+        } => print_flags(
+            filepath,
+            components,
+            configuration.as_deref(),
+            search_paths,
+            *cflags,
+            *libs,
+        ),
     }
 }
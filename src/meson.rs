@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::cps::{load_cps, Package};
+use crate::flags::{collect_flags, install_prefix};
+
+/// Quote a value as a Meson string literal
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn array(values: &[String]) -> String {
+    let values: Vec<_> = values.iter().map(|value| quote(value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// The Meson variable holding the dependency of `package`, e.g. `gtk_3_0_dep` for `gtk-3.0`
+fn variable_name(package: &Package) -> String {
+    let name: String = package
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_dep", name)
+}
+
+/// Render a `declare_dependency(...)` of the default components of `package`
+///
+/// The `-I` compile flags become `include_directories`, the other compile flags
+/// `compile_args` and the link flags `link_args`. The packages the package requires become
+/// `dependencies` found by name. `@prefix@` is replaced by `prefix`, which must be known if the
+/// package uses it.
+pub fn meson_dependency(package: &Package, prefix: Option<&Path>) -> Result<String> {
+    let flags = collect_flags(package, &[], prefix)?;
+    let (includes, compile_args): (Vec<_>, Vec<_>) = flags
+        .cflags
+        .into_iter()
+        .partition(|flag| flag.starts_with("-I"));
+    let includes: Vec<_> = includes
+        .iter()
+        .map(|flag| flag["-I".len()..].to_string())
+        .collect();
+    let mut requires: Vec<_> = package.requires.iter().flatten().map(|(n, _)| n).collect();
+    requires.sort();
+
+    let mut arguments = Vec::new();
+    if !includes.is_empty() {
+        arguments.push(format!(
+            "include_directories: include_directories({})",
+            includes
+                .iter()
+                .map(|include| quote(include))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if !compile_args.is_empty() {
+        arguments.push(format!("compile_args: {}", array(&compile_args)));
+    }
+    if !flags.libs.is_empty() {
+        arguments.push(format!("link_args: {}", array(&flags.libs)));
+    }
+    if !requires.is_empty() {
+        let dependencies: Vec<_> = requires
+            .iter()
+            .map(|name| format!("dependency({})", quote(name)))
+            .collect();
+        arguments.push(format!("dependencies: [{}]", dependencies.join(", ")));
+    }
+    if let Some(version) = &package.version {
+        arguments.push(format!("version: {}", quote(version)));
+    }
+
+    let mut meson = format!("{} = declare_dependency(\n", variable_name(package));
+    for argument in arguments {
+        meson.push_str(&format!("  {},\n", argument));
+    }
+    meson.push_str(")\n");
+    Ok(meson)
+}
+
+/// Print the `declare_dependency(...)` of the cps file at `cps`, see [`meson_dependency`]
+///
+/// The install prefix is `prefix` if given and otherwise found from the location of the cps
+/// file, see [`install_prefix`].
+pub fn print_meson(cps: &Path, prefix: Option<&Path>) -> Result<()> {
+    let package = load_cps(cps)?;
+    let prefix = prefix
+        .map(PathBuf::from)
+        .or_else(|| install_prefix(&package, cps));
+    print!("{}", meson_dependency(&package, prefix.as_deref())?);
+    Ok(())
+}
+
+#[test]
+fn test_meson_dependency() -> Result<()> {
+    let package: Package = r#"{
+        "name": "foo-1.0",
+        "cps_version": "0.11.0",
+        "version": "1.0.2",
+        "default_components": ["foo"],
+        "requires": { "zlib": {}, "bar": {} },
+        "components": {
+            "foo": {
+                "type": "dylib",
+                "location": "@prefix@/lib/libfoo.so",
+                "requires": [":foo-core", "bar", "zlib"],
+                "link_flags": ["-Wl,--as-needed"]
+            },
+            "foo-core": {
+                "type": "interface",
+                "includes": ["@prefix@/include/foo"],
+                "definitions": ["FOO_API=1"],
+                "compile_flags": ["-pthread"]
+            }
+        }
+    }"#
+    .parse()?;
+
+    assert_eq!(
+        meson_dependency(&package, Some(Path::new("/opt/foo")))?,
+        "foo_1_0_dep = declare_dependency(
+  include_directories: include_directories('/opt/foo/include/foo'),
+  compile_args: ['-DFOO_API=1', '-pthread'],
+  link_args: ['-L/opt/foo/lib', '-lfoo', '-Wl,--as-needed'],
+  dependencies: [dependency('bar'), dependency('zlib')],
+  version: '1.0.2',
+)
+"
+    );
+    assert!(meson_dependency(&package, None).is_err());
+    Ok(())
+}
@@ -1,31 +1,93 @@
-use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
 
 use regex::Regex;
 
-#[derive(Default, Debug, PartialEq, Eq)]
+use crate::Error;
+
+/// A version comparison like the `>= 1.0` of `foo >= 1.0`
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionConstraint {
+    pub op: String,
+    /// `None` when the operator isn't followed by a version
+    pub version: Option<String>,
+}
+
+/// Formats like pkg-config, e.g. `>= 1.0`
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.op, version),
+            None => write!(f, "{}", self.op),
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Serialize)]
 pub struct Dependency {
     pub name: String,
-    pub op: Option<String>,
-    pub version: Option<String>,
+    /// Every constraint on the version, e.g. both bounds of `foo >= 1.0, foo < 2.0`
+    pub constraints: Vec<VersionConstraint>,
 }
 
 impl Dependency {
-    fn parse_list(data: &str) -> Vec<Self> {
-        let re = Regex::new(r"([^ ,<=>!]+)[ ]*(([<=>!]+)[ ]*([^ ,]+)?)?").unwrap();
-        re.captures_iter(data)
-            .flat_map(|c| -> Result<Self> {
-                Ok(Self {
-                    name: c
-                        .get(1)
-                        .ok_or(anyhow!("captured dependency without name: {:?}", c))?
-                        .as_str()
-                        .to_string(),
-                    op: c.get(3).map(|m| m.as_str().to_string()),
-                    version: c.get(4).map(|m| m.as_str().to_string()),
-                })
-            })
-            .collect()
+    /// Parse a dependency list like the value of `Requires`
+    pub fn parse_list(data: &str) -> Vec<Self> {
+        Self::parse_list_reporting("", data, &mut Vec::new())
+    }
+
+    /// Parse a dependency list, warning about any text that isn't part of a dependency
+    ///
+    /// Stray separators are skipped, an operator without a version is kept without one and a
+    /// version compared against no name is dropped. Repeated names are merged into a single
+    /// dependency with the constraints of each.
+    fn parse_list_reporting(property: &str, data: &str, warnings: &mut Vec<Warning>) -> Vec<Self> {
+        // Any whitespace separates, so tabs and the newlines of joined lines do as spaces do
+        let re = Regex::new(r"([^\s,<=>!]+)\s*(([<=>!]+)\s*([^\s,]+)?)?").unwrap();
+        let mut unparsed = |entry: &str| {
+            let entry = entry.trim_matches(|c: char| c.is_whitespace() || c == ',');
+            if !entry.is_empty() {
+                warnings.push(Warning::MalformedDependency {
+                    property: property.to_string(),
+                    entry: entry.to_string(),
+                });
+            }
+        };
+        let mut dependencies: Vec<Self> = Vec::new();
+        let mut end = 0;
+        for c in re.captures_iter(data) {
+            let whole = c.get(0).unwrap();
+            let skipped = &data[end..whole.start()];
+            end = whole.end();
+            // the name is really the version of a dangling operator, e.g. the `1.0` of `, >= 1.0`
+            if skipped.contains(['<', '=', '>', '!']) {
+                unparsed(&format!("{}{}", skipped, whole.as_str()));
+                continue;
+            }
+            unparsed(skipped);
+            // an operator without a version to compare against
+            if c.get(3).is_some() && c.get(4).is_none() {
+                unparsed(whole.as_str());
+            }
+            let constraint = c.get(3).map(|op| VersionConstraint {
+                op: op.as_str().to_string(),
+                version: c.get(4).map(|m| m.as_str().to_string()),
+            });
+            match dependencies.iter_mut().find(|d| d.name == c[1]) {
+                Some(dependency) => dependency.constraints.extend(constraint),
+                None => dependencies.push(Self {
+                    name: c[1].to_string(),
+                    constraints: constraint.into_iter().collect(),
+                }),
+            }
+        }
+        unparsed(&data[end..]);
+        dependencies
     }
 
     pub fn from_name(name: &str) -> Self {
@@ -38,46 +100,235 @@ impl Dependency {
     pub fn with_version(name: &str, op: &str, version: &str) -> Self {
         Self {
             name: name.to_string(),
-            op: Some(op.to_string()),
-            version: Some(version.to_string()),
+            constraints: vec![VersionConstraint {
+                op: op.to_string(),
+                version: Some(version.to_string()),
+            }],
         }
     }
 }
 
-#[derive(Default, Debug, PartialEq, Eq)]
+/// A `-D` compile definition, split into its name and value if it has one
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Definition {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl Definition {
+    /// Parse a definition without its `-D` prefix, e.g. `VERSION=2`
+    pub fn parse(definition: &str) -> Self {
+        match definition.split_once('=') {
+            Some((name, value)) => Self {
+                name: name.to_string(),
+                value: Some(value.to_string()),
+            },
+            None => Self {
+                name: definition.to_string(),
+                value: None,
+            },
+        }
+    }
+}
+
+/// Formats as `NAME` or `NAME=value`, the form of CPS definitions
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Options controlling how pc files are parsed
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// Fail on a reference to an undefined variable instead of leaving it in place
+    pub strict: bool,
+    /// Variables overriding those defined in the file, like pkg-config's `--define-variable`
+    pub define_variables: HashMap<String, String>,
+    /// Record the flag each value of `Cflags` and `Libs` was parsed from in
+    /// [`PkgConfigFile::sources`]
+    pub record_sources: bool,
+    /// Define `prefix` as the install prefix of the pc file, like pkg-config's
+    /// `--define-prefix`, see [`ParseOptions::for_file`]
+    pub define_prefix: bool,
+}
+
+impl ParseOptions {
+    /// The options to parse the pc file at `path` with
+    ///
+    /// With [`ParseOptions::define_prefix`] the `prefix` variable is the [`relocated_prefix`]
+    /// of the file instead of the value in the file, unless it is in
+    /// [`ParseOptions::define_variables`].
+    pub fn for_file(&self, path: &Path) -> Self {
+        let mut options = self.clone();
+        if self.define_prefix && !self.define_variables.contains_key("prefix") {
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+            if let Some(prefix) = relocated_prefix(&path) {
+                options
+                    .define_variables
+                    .insert("prefix".to_string(), prefix.display().to_string());
+            }
+        }
+        options
+    }
+}
+
+/// The install prefix of the pc file at `path`, two levels above the `pkgconfig` directory
+/// holding it, e.g. `/opt/foo` for `/opt/foo/lib/pkgconfig/foo.pc`
+pub fn relocated_prefix(path: &Path) -> Option<&Path> {
+    let pkgconfig_dir = path.parent()?;
+    if pkgconfig_dir.file_name()? != "pkgconfig" {
+        return None;
+    }
+    pkgconfig_dir.parent()?.parent()
+}
+
+/// A value parsed from a flag of a pc file, see [`ParseOptions::record_sources`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlagSource {
+    /// The property holding the flag, e.g. `Cflags`
+    pub property: String,
+    /// The flag as written, e.g. `-isystem /opt/include`
+    pub flag: String,
+    /// The value parsed from the flag, e.g. `/opt/include`, or the flag itself if it has none
+    pub value: String,
+}
+
+/// Something in a pc file that parsing couldn't make sense of and silently worked around
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Warning {
+    /// A `${variable}` that is never defined, left in the parsed values literally
+    UnexpandedVariable(String),
+    /// Text in a dependency list that isn't a dependency, or a version operator without a version
+    MalformedDependency { property: String, entry: String },
+    /// A property that is present without a value
+    EmptyProperty(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnexpandedVariable(name) => {
+                write!(
+                    f,
+                    "variable `{}` is not defined and was left unexpanded",
+                    name
+                )
+            }
+            Warning::MalformedDependency { property, entry } => {
+                write!(f, "could not parse `{}` in `{}`", entry, property)
+            }
+            Warning::EmptyProperty(name) => write!(f, "property `{}` has no value", name),
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Serialize)]
 pub struct PkgConfigFile {
     pub name: String,
     pub version: String,
     pub description: String,
     pub url: Option<String>,
     pub includes: Vec<String>,
-    pub definitions: Vec<String>,
+    pub definitions: Vec<Definition>,
     pub compile_flags: Vec<String>,
-    pub cflags_private: Option<String>,
+    /// `-I` includes of `Cflags.private`, only needed for static linking
+    pub includes_private: Vec<String>,
+    /// `-D` definitions of `Cflags.private`, only needed for static linking
+    pub definitions_private: Vec<Definition>,
+    pub compile_flags_private: Vec<String>,
     pub copyright: Option<String>,
     pub link_locations: Vec<String>,
     pub link_libraries: Vec<String>,
+    /// The `Libs` flags other than `-L` and `-l`, in the order they appear, so the position of
+    /// a flag like `-Wl,--as-needed` relative to the others is kept
     pub link_flags: Vec<String>,
-    pub libs_private: Option<String>,
+    /// macOS frameworks linked with `-framework`, which are also kept in place in `link_flags`
+    pub frameworks: Vec<String>,
+    /// `-L` locations of `Libs.private`, only needed for static linking
+    pub link_locations_private: Vec<String>,
+    /// `-l` libraries of `Libs.private`, only needed for static linking
+    pub link_libraries_private: Vec<String>,
+    pub link_flags_private: Vec<String>,
     pub license: Option<String>,
     pub maintainer: Option<String>,
     pub requires: Vec<Dependency>,
     pub requires_private: Vec<Dependency>,
+    /// Nonstandard `Requires.internal` emitted by some toolchains for build-only dependencies
+    pub requires_internal: Vec<Dependency>,
     pub conflicts: Vec<Dependency>,
     pub provides: Vec<Dependency>,
+    /// The properties pkg-config doesn't know of, e.g. `X-Homepage`, by name
+    pub extra: HashMap<String, String>,
+    /// The flags of `Cflags` and `Libs` with the values parsed from them, only recorded with
+    /// [`ParseOptions::record_sources`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<FlagSource>,
 }
 
 impl PkgConfigFile {
-    pub fn parse(data: &str) -> Result<Self> {
-        let data = strip_comments(data);
-        let data = expand_variables(&data, 0)?;
-
-        let name =
-            capture_property("Name", &data)?.ok_or(anyhow!("missing required property `Name`"))?;
-        let version = capture_property("Version", &data)?
-            .ok_or(anyhow!("missing required property `Version`"))?;
-        let description = capture_property("Description", &data)?
-            .ok_or(anyhow!("missing required property `Description`"))?;
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        Self::parse_traced(data, None)
+    }
+
+    /// Parse, also returning what had to be worked around to do so
+    pub fn parse_with_warnings(data: &str) -> Result<(Self, Vec<Warning>), Error> {
+        Self::parse_with_options(data, &ParseOptions::default(), None)
+    }
+
+    /// Parse, writing each variable expansion pass and the final variables to `trace`
+    pub fn parse_traced(data: &str, trace: Option<&mut dyn Write>) -> Result<Self, Error> {
+        Ok(Self::parse_with_options(data, &ParseOptions::default(), trace)?.0)
+    }
+
+    /// Parse with `overrides` replacing the variables of the same name defined in the file,
+    /// e.g. to relocate a package by overriding its `prefix`
+    pub fn parse_with_overrides(
+        data: &str,
+        overrides: HashMap<String, String>,
+    ) -> Result<Self, Error> {
+        let options = ParseOptions {
+            define_variables: overrides,
+            ..ParseOptions::default()
+        };
+        Ok(Self::parse_with_options(data, &options, None)?.0)
+    }
+
+    /// Parse according to `options`, returning the warnings and writing the variable expansion
+    /// to `trace` if given
+    pub fn parse_with_options(
+        data: &str,
+        options: &ParseOptions,
+        trace: Option<&mut dyn Write>,
+    ) -> Result<(Self, Vec<Warning>), Error> {
+        // files written on Windows may start with a byte order mark and end lines with CRLF
+        let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+        let data = strip_comments(&data.replace("\r\n", "\n"));
+        let data = expand_variables(&data, &options.define_variables, trace)?;
+        let unexpanded = unexpanded_variables(&data);
+        if let (true, Some(name)) = (options.strict, unexpanded.first()) {
+            return Err(Error::UndefinedVariable(name.clone()));
+        }
+        let mut warnings: Vec<_> = unexpanded
+            .into_iter()
+            .map(Warning::UnexpandedVariable)
+            .collect();
+        let empty = empty_properties(&data);
+        warnings.extend(empty.iter().cloned().map(Warning::EmptyProperty));
+
+        let missing = |name: &str| Error::MissingProperty(name.to_string());
+        let name = capture_property("Name", &data)?.ok_or_else(|| missing("Name"))?;
+        // pkg-config accepts a `Version:` without a value, only a missing one is an error
+        let version = match capture_property("Version", &data)? {
+            Some(version) => version,
+            None if empty.iter().any(|property| property == "Version") => String::new(),
+            None => return Err(missing("Version")),
+        };
+        let description =
+            capture_property("Description", &data)?.ok_or_else(|| missing("Description"))?;
         let url = capture_property("URL", &data)?;
         let cflags = capture_property("Cflags", &data)?;
         let cflags_private = capture_property("Cflags.private", &data)?;
@@ -88,36 +339,66 @@ impl PkgConfigFile {
         let maintainer = capture_property("Maintainer", &data)?;
         let requires = capture_property("Requires", &data)?.unwrap_or_default();
         let requires_private = capture_property("Requires.private", &data)?.unwrap_or_default();
+        let requires_internal = capture_property("Requires.internal", &data)?.unwrap_or_default();
         let conflicts = capture_property("Conflicts", &data)?.unwrap_or_default();
         let provides = capture_property("Provides", &data)?.unwrap_or_default();
+        let extra = extra_properties(&data);
 
         // process cflags
-        let cflags: Vec<_> = cflags
-            .unwrap_or_default()
-            .split_whitespace()
-            .map(String::from)
-            .collect();
-        let includes = filter_flag(&cflags, "-I");
-        let definitions = filter_flag(&cflags, "-D");
-        let compile_flags = filter_excluding_flags(&cflags, &["-I", "-D"]);
+        let cflags = group_flags(split_flags(&cflags.unwrap_or_default()));
+        let includes = parse_includes(&cflags);
+        let definitions = parse_definitions(&cflags);
+        let mut compile_flags = filter_excluding_flags(&cflags, &["-I", "-isystem", "-D"]);
+
+        // process private cflags
+        let cflags_private = group_flags(split_flags(&cflags_private.unwrap_or_default()));
+        let includes_private = parse_includes(&cflags_private);
+        let definitions_private = parse_definitions(&cflags_private);
+        let mut compile_flags_private =
+            filter_excluding_flags(&cflags_private, &["-I", "-isystem", "-D"]);
 
         // process libs
-        let libs: Vec<_> = libs
-            .unwrap_or_default()
-            .split_whitespace()
-            .map(String::from)
-            .collect();
+        let libs = group_flags(split_flags(&libs.unwrap_or_default()));
         let link_locations = filter_flag(&libs, "-L");
         let link_libraries = filter_flag(&libs, "-l");
-        let link_flags = filter_excluding_flags(&libs, &["-L", "-l"]);
+        let frameworks = filter_argument(&libs, "-framework");
+        let mut link_flags = filter_excluding_flags(&libs, &["-L", "-l"]);
+        share_compile_and_link_flags(&mut compile_flags, &mut link_flags);
+
+        // process private libs
+        let libs_private = group_flags(split_flags(&libs_private.unwrap_or_default()));
+        let link_locations_private = filter_flag(&libs_private, "-L");
+        let link_libraries_private = filter_flag(&libs_private, "-l");
+        let mut link_flags_private = filter_excluding_flags(&libs_private, &["-L", "-l"]);
+        share_compile_and_link_flags(&mut compile_flags_private, &mut link_flags_private);
+
+        let sources = if options.record_sources {
+            [
+                ("Cflags", &cflags),
+                ("Cflags.private", &cflags_private),
+                ("Libs", &libs),
+                ("Libs.private", &libs_private),
+            ]
+            .into_iter()
+            .flat_map(|(property, flags)| flag_sources(property, flags))
+            .collect()
+        } else {
+            Vec::new()
+        };
 
         // process requires
-        let requires = Dependency::parse_list(&requires);
-        let requires_private = Dependency::parse_list(&requires_private);
-        let conflicts = Dependency::parse_list(&conflicts);
-        let provides = Dependency::parse_list(&provides);
+        let requires = Dependency::parse_list_reporting("Requires", &requires, &mut warnings);
+        let requires_private =
+            Dependency::parse_list_reporting("Requires.private", &requires_private, &mut warnings);
+        let requires_internal = Dependency::parse_list_reporting(
+            "Requires.internal",
+            &requires_internal,
+            &mut warnings,
+        );
+        let conflicts = Dependency::parse_list_reporting("Conflicts", &conflicts, &mut warnings);
+        let provides = Dependency::parse_list_reporting("Provides", &provides, &mut warnings);
 
-        Ok(Self {
+        let pkg_config = Self {
             name,
             version,
             description,
@@ -125,28 +406,109 @@ impl PkgConfigFile {
             includes,
             definitions,
             compile_flags,
-            cflags_private,
+            includes_private,
+            definitions_private,
+            compile_flags_private,
             copyright,
             link_locations,
             link_libraries,
             link_flags,
-            libs_private,
+            frameworks,
+            link_locations_private,
+            link_libraries_private,
+            link_flags_private,
             license,
             maintainer,
             requires,
             requires_private,
+            requires_internal,
             conflicts,
             provides,
-        })
+            extra,
+            sources,
+        };
+        Ok((pkg_config, warnings))
+    }
+}
+
+/// Read a pc file, or stdin if the path is `-`
+pub fn read_pc_file(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut data = String::new();
+        std::io::stdin().read_to_string(&mut data)?;
+        Ok(data)
+    } else {
+        Ok(std::fs::read_to_string(path)?)
     }
 }
 
-fn capture_property(name: &str, data: &str) -> Result<Option<String>> {
-    Ok(Regex::new(&format!(r"{}:[ ]+(.+)", name))?
+/// Parse a pc file and print the result as JSON, showing how it is interpreted before any
+/// conversion to CPS
+///
+/// Any parse warnings are printed to stderr.
+pub fn print_pkg_config_info(path: &Path) -> Result<()> {
+    let (pkg_config, warnings) = PkgConfigFile::parse_with_warnings(&read_pc_file(path)?)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    println!("{}", serde_json::to_string_pretty(&pkg_config)?);
+    Ok(())
+}
+
+fn capture_property(name: &str, data: &str) -> Result<Option<String>, Error> {
+    Ok(Regex::new(&format!(r"{}:[ \t]+(.+)", name))?
         .captures(data)
         .map(|cap| cap[1].trim().to_string()))
 }
 
+/// Names of the `${variable}` references left after expansion, without duplicates
+fn unexpanded_variables(data: &str) -> Vec<String> {
+    let re = Regex::new(r"\$\{([^}]*)\}").unwrap();
+    let mut names: Vec<String> = Vec::new();
+    for c in re.captures_iter(data) {
+        if !names.iter().any(|name| name == &c[1]) {
+            names.push(c[1].to_string());
+        }
+    }
+    names
+}
+
+/// The properties of a pc file that pkg-config knows of
+const KNOWN_PROPERTIES: &[&str] = &[
+    "Name",
+    "Description",
+    "Version",
+    "URL",
+    "Cflags",
+    "Cflags.private",
+    "Copyright",
+    "Libs",
+    "Libs.private",
+    "License",
+    "Maintainer",
+    "Requires",
+    "Requires.private",
+    "Requires.internal",
+    "Conflicts",
+    "Provides",
+];
+
+/// The properties with a value that are not [`KNOWN_PROPERTIES`], e.g. vendor ones like
+/// `X-Homepage`
+fn extra_properties(data: &str) -> HashMap<String, String> {
+    let re = Regex::new(r"(?m)^([A-Za-z][A-Za-z0-9_.\-]*):[ \t]+(.+)$").unwrap();
+    re.captures_iter(data)
+        .filter(|c| !KNOWN_PROPERTIES.contains(&&c[1]))
+        .map(|c| (c[1].to_string(), c[2].trim().to_string()))
+        .collect()
+}
+
+/// Names of the properties declared without a value, e.g. `Libs.private:`
+fn empty_properties(data: &str) -> Vec<String> {
+    let re = Regex::new(r"(?m)^([A-Za-z][A-Za-z.]*):[ ]*$").unwrap();
+    re.captures_iter(data).map(|c| c[1].to_string()).collect()
+}
+
 fn strip_comments(data: &str) -> String {
     data.lines()
         .filter(|line| !line.starts_with('#'))
@@ -154,58 +516,331 @@ fn strip_comments(data: &str) -> String {
         .join("\n")
 }
 
-fn parse_variables(data: &str) -> HashMap<String, String> {
-    let re = Regex::new(r"([a-zA-Z0-9\-_]+)[ ]*=[ ]*([:a-zA-Z0-9\-_/=\.+ ]*)?$").unwrap();
+fn write_variables(
+    trace: &mut dyn Write,
+    variables: &HashMap<String, String>,
+) -> Result<(), Error> {
+    for (name, value) in variables.iter().collect::<BTreeMap<_, _>>() {
+        writeln!(trace, "  {} = {}", name, value)?;
+    }
+    Ok(())
+}
+
+/// Expand the variables defined in `data`, with those of `overrides` taking precedence
+///
+/// Each variable is resolved exactly once, after the variables its value refers to, so a long
+/// chain of variables costs no more than a flat list. References to undefined variables are left
+/// in place, variables that refer back to themselves are an error.
+fn expand_variables(
+    data: &str,
+    overrides: &HashMap<String, String>,
+    trace: Option<&mut dyn Write>,
+) -> Result<String, Error> {
+    let mut definitions = variable_definitions(data);
+    definitions.extend(overrides.clone());
 
-    data.lines()
-        .flat_map(|line| re.captures_iter(line))
-        .flat_map(|c| {
-            let name = c.get(1).map(|m| m.as_str().to_string())?;
-            let value = c.get(2).map(|m| m.as_str().to_string()).unwrap_or_default();
-            Some((name, value))
-        })
+    let cyclic = cyclic_variables(&definitions);
+    if !cyclic.is_empty() {
+        let mut undefined: Vec<_> = variable_references(data)
+            .into_iter()
+            .filter(|name| !definitions.contains_key(name))
+            .collect();
+        undefined.sort();
+        return Err(Error::UnresolvableVariables { cyclic, undefined });
+    }
+
+    let order = resolution_order(&definitions);
+    let mut resolved = HashMap::new();
+    for name in &order {
+        let value = substitute_variables(&definitions[name], &resolved);
+        resolved.insert(name.clone(), value);
+    }
+    if let Some(trace) = trace {
+        writeln!(trace, "resolution order:")?;
+        for name in &order {
+            writeln!(trace, "  {} = {}", name, resolved[name])?;
+        }
+        writeln!(trace, "final variables:")?;
+        write_variables(trace, &resolved)?;
+    }
+    Ok(substitute_variables(data, &resolved))
+}
+
+/// The value of each variable defined in `data` as written, e.g. `${prefix}/lib` for
+/// `libdir=${prefix}/lib`
+fn variable_definitions(data: &str) -> HashMap<String, String> {
+    let re = Regex::new(r"(?m)^([a-zA-Z0-9\-_]+)[ ]*=(.*)$").unwrap();
+    re.captures_iter(data)
+        .map(|c| (c[1].to_string(), c[2].trim().to_string()))
         .collect()
 }
 
-fn expand_variables(data: &str, index: i32) -> Result<String> {
-    let variables = parse_variables(data);
+fn variable_reference_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\$\{([^}]*)\}|\$\(([^)]*)\)").unwrap())
+}
+
+/// Names of the variables `value` refers to with `${variable}` or `$(variable)`, without
+/// duplicates
+fn variable_references(value: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    variable_reference_regex()
+        .captures_iter(value)
+        .map(|c| c.get(1).or(c.get(2)).map_or("", |name| name.as_str()))
+        .filter(|&name| seen.insert(name))
+        .map(String::from)
+        .collect()
+}
 
-    if index > 100 {
-        return Err(anyhow!(
-            "Max recursion hit expanding variables\n\n{}\n\n{:?}",
-            data,
+/// Replace the references to the `variables` in `value` with their value
+fn substitute_variables(value: &str, variables: &HashMap<String, String>) -> String {
+    variable_reference_regex()
+        .replace_all(value, |c: &regex::Captures| {
+            let name = c.get(1).or(c.get(2)).map_or("", |name| name.as_str());
             variables
-        ));
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| c[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The variables of `definitions` whose value refers back to themselves, in name order
+///
+/// They are the variables of the groups that all reach each other, found by following the
+/// references backwards from each variable in reverse [`resolution_order`].
+fn cyclic_variables(definitions: &HashMap<String, String>) -> Vec<String> {
+    let mut referrers = HashMap::<String, Vec<&str>>::new();
+    for (name, value) in definitions {
+        for reference in variable_references(value) {
+            referrers.entry(reference).or_default().push(name);
+        }
     }
 
-    let mut data = data.to_string();
-    for (key, value) in variables {
-        // ${variable} syntax
-        let from = format!("${{{}}}", key);
-        data = data.replace(&from, &value);
+    let mut cyclic = Vec::new();
+    let mut grouped = HashSet::new();
+    for name in resolution_order(definitions).iter().rev() {
+        if !grouped.insert(name.as_str()) {
+            continue;
+        }
+        let mut group = Vec::new();
+        let mut pending = vec![name.as_str()];
+        while let Some(name) = pending.pop() {
+            group.push(name);
+            for &referrer in referrers.get(name).into_iter().flatten() {
+                if grouped.insert(referrer) {
+                    pending.push(referrer);
+                }
+            }
+        }
+        if group.len() > 1 || variable_references(&definitions[name]).contains(name) {
+            cyclic.extend(group.into_iter().map(String::from));
+        }
+    }
+    cyclic.sort();
+    cyclic
+}
 
-        // $(variable) syntax
-        let from = format!("$({})", key);
-        data = data.replace(&from, &value);
+/// The variables of `definitions`, each after the variables it refers to unless those refer
+/// back to it
+///
+/// The references are followed with an explicit stack, so a long chain of variables can't
+/// overflow the call stack.
+fn resolution_order(definitions: &HashMap<String, String>) -> Vec<String> {
+    let mut names: Vec<_> = definitions.keys().collect();
+    names.sort();
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    // each name with whether the variables it refers to are already in the order
+    let mut pending: Vec<_> = names
+        .into_iter()
+        .rev()
+        .map(|name| (name.clone(), false))
+        .collect();
+    while let Some((name, referenced)) = pending.pop() {
+        if referenced {
+            order.push(name);
+            continue;
+        }
+        let Some(value) = definitions.get(&name) else {
+            continue;
+        };
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        let references = variable_references(value);
+        pending.push((name, true));
+        pending.extend(
+            references
+                .into_iter()
+                .rev()
+                .map(|reference| (reference, false)),
+        );
     }
+    order
+}
 
-    if data.contains("${") {
-        expand_variables(&data, index + 1)
-    } else {
-        Ok(data)
+/// Split a flags property into arguments like a shell would, honoring quotes and backslash
+/// escapes so that e.g. `-DPATH="/x y"` stays a single flag
+fn split_flags(data: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut flag = String::new();
+    let mut in_flag = false;
+    let mut quote = None;
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => match chars.next() {
+                Some(escaped @ ('"' | '\\' | '$' | '`')) => flag.push(escaped),
+                Some(other) => flag.extend(['\\', other]),
+                None => flag.push('\\'),
+            },
+            (Some(_), c) => flag.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_flag = true;
+            }
+            (None, '\\') => {
+                flag.extend(chars.next());
+                in_flag = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_flag {
+                    flags.push(std::mem::take(&mut flag));
+                    in_flag = false;
+                }
+            }
+            (None, c) => {
+                flag.push(c);
+                in_flag = true;
+            }
+        }
+    }
+    if in_flag {
+        flags.push(flag);
+    }
+    flags
+}
+
+/// Flags that matter to both compiling and linking
+const COMPILE_AND_LINK_FLAGS: &[&str] = &["-pthread", "-pthreads"];
+
+/// Add the flags of [`COMPILE_AND_LINK_FLAGS`] found in either list to the other one
+fn share_compile_and_link_flags(compile_flags: &mut Vec<String>, link_flags: &mut Vec<String>) {
+    for flag in COMPILE_AND_LINK_FLAGS {
+        let in_compile = compile_flags.iter().any(|f| f == flag);
+        let in_link = link_flags.iter().any(|f| f == flag);
+        if in_compile && !in_link {
+            link_flags.push(flag.to_string());
+        } else if in_link && !in_compile {
+            compile_flags.push(flag.to_string());
+        }
     }
 }
 
-fn filter_flag(data: &[String], flag: &str) -> Vec<String> {
+/// Flags whose argument is the next token, e.g. `-Xlinker -rpath`
+const FLAGS_WITH_ARGUMENT: &[&str] = &[
+    "-Xlinker",
+    "-Xcompiler",
+    "-Xpreprocessor",
+    "-framework",
+    "-weak_framework",
+    "-include",
+    "-isystem",
+    "-idirafter",
+    "-iquote",
+];
+
+/// Group each flag with its argument when that is a separate token, so an argument like the
+/// `-L/opt/lib` of `-Xlinker -L/opt/lib` is not mistaken for a flag of its own
+pub(crate) fn group_flags(flags: Vec<String>) -> Vec<Vec<String>> {
+    let mut groups = Vec::new();
+    let mut flags = flags.into_iter();
+    while let Some(flag) = flags.next() {
+        let takes_argument = FLAGS_WITH_ARGUMENT.contains(&flag.as_str());
+        let mut group = vec![flag];
+        if takes_argument {
+            group.extend(flags.next());
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Directories of the `-I` and `-isystem` flags, CPS has no notion of system includes so both
+/// are plain includes
+fn parse_includes(cflags: &[Vec<String>]) -> Vec<String> {
+    cflags
+        .iter()
+        .filter_map(|group| match group.as_slice() {
+            [flag, dir] if flag == "-isystem" => Some(dir.clone()),
+            [flag] => flag
+                .strip_prefix("-I")
+                .or_else(|| flag.strip_prefix("-isystem"))
+                .filter(|dir| !dir.is_empty())
+                .map(String::from),
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_definitions(cflags: &[Vec<String>]) -> Vec<Definition> {
+    filter_flag(cflags, "-D")
+        .iter()
+        .map(|definition| Definition::parse(definition))
+        .collect()
+}
+
+/// Where each flag group of `property` came from, with the value it is parsed into, e.g. the
+/// directory of `-I/opt/include` or `-isystem /opt/include`
+fn flag_sources(property: &str, flags: &[Vec<String>]) -> Vec<FlagSource> {
+    flags
+        .iter()
+        .map(|group| {
+            let value = match group.as_slice() {
+                [_, argument] => argument.clone(),
+                [flag] => ["-I", "-isystem", "-D", "-L", "-l"]
+                    .iter()
+                    .find_map(|prefix| flag.strip_prefix(prefix))
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or(flag)
+                    .to_string(),
+                _ => group.join(" "),
+            };
+            FlagSource {
+                property: property.to_string(),
+                flag: group.join(" "),
+                value,
+            }
+        })
+        .collect()
+}
+
+/// The values of the flags starting with `flag`, skipping grouped flags and their arguments
+fn filter_flag(data: &[Vec<String>], flag: &str) -> Vec<String> {
     data.iter()
-        .filter(|&s| s.starts_with(flag))
-        .map(|l| String::from(&l[flag.len()..]))
+        .filter_map(|group| match group.as_slice() {
+            [s] if s.starts_with(flag) => Some(String::from(&s[flag.len()..])),
+            _ => None,
+        })
         .collect::<Vec<_>>()
 }
 
-fn filter_excluding_flags(data: &[String], flags: &[&str]) -> Vec<String> {
+/// The arguments of a flag taking a separate one, like the `Foo` of `-framework Foo`
+fn filter_argument(data: &[Vec<String>], flag: &str) -> Vec<String> {
     data.iter()
-        .filter(|&s| !flags.iter().any(|f| s.starts_with(f)))
+        .filter_map(|group| match group.as_slice() {
+            [f, argument] if f == flag => Some(argument.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn filter_excluding_flags(data: &[Vec<String>], flags: &[&str]) -> Vec<String> {
+    data.iter()
+        .filter(|&group| !flags.iter().any(|f| group[0].starts_with(f)))
+        .flatten()
         .map(String::from)
         .collect::<Vec<_>>()
 }
@@ -284,6 +919,546 @@ Cflags: -I${includedir}
     Ok(())
 }
 
+#[test]
+fn test_info_json() -> Result<()> {
+    let pkg_config = PkgConfigFile::parse(include_str!("../testdata/fcl.pc"))?;
+    let json = serde_json::to_value(&pkg_config)?;
+
+    assert_eq!(json["name"], "fcl");
+    assert_eq!(json["version"], "0.7.0");
+    assert_eq!(json["includes"], serde_json::json!(["/usr/include"]));
+    assert_eq!(json["compile_flags"], serde_json::json!(["-std=c++11"]));
+    assert_eq!(
+        json["link_locations"],
+        serde_json::json!(["/usr/lib/x86_64-linux-gnu"])
+    );
+    assert_eq!(json["link_libraries"], serde_json::json!(["fcl"]));
+    assert_eq!(
+        json["requires"][0],
+        serde_json::json!({ "name": "ccd", "constraints": [] })
+    );
+    assert_eq!(json["url"], serde_json::Value::Null);
+    Ok(())
+}
+
+#[test]
+fn test_parse_requires_internal() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar
+Requires.private: baz
+Requires.internal: gtest >= 1.10 benchmark
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(pkg_config.requires, vec![Dependency::from_name("bar")]);
+    assert_eq!(
+        pkg_config.requires_private,
+        vec![Dependency::from_name("baz")]
+    );
+    assert_eq!(
+        pkg_config.requires_internal,
+        vec![
+            Dependency::with_version("gtest", ">=", "1.10"),
+            Dependency::from_name("benchmark"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_trace_variable_expansion() -> Result<()> {
+    let data = r#"
+prefix=/usr
+exec_prefix=${prefix}
+libdir=${exec_prefix}/lib
+
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Libs: -L${libdir} -lfoo
+    "#;
+
+    let mut trace = Vec::new();
+    PkgConfigFile::parse_traced(data, Some(&mut trace))?;
+    let trace = String::from_utf8(trace)?;
+
+    // libdir depends on exec_prefix which depends on prefix, so they resolve in reverse
+    assert!(
+        trace.starts_with(
+            "resolution order:\n  prefix = /usr\n  exec_prefix = /usr\n  libdir = /usr/lib\n"
+        ),
+        "{}",
+        trace
+    );
+    let final_variables = trace
+        .split("final variables:\n")
+        .nth(1)
+        .expect("trace is missing the final variables");
+    assert_eq!(
+        final_variables,
+        "  exec_prefix = /usr\n  libdir = /usr/lib\n  prefix = /usr\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_empty_version() -> Result<()> {
+    let data = "Name: foo\nDescription: Foo library\nVersion:\nCflags: -I/usr/include/foo\n";
+    let (pkg_config, warnings) =
+        PkgConfigFile::parse_with_options(data, &ParseOptions::default(), None)?;
+    assert_eq!(pkg_config.version, "");
+    assert_eq!(
+        warnings,
+        vec![Warning::EmptyProperty("Version".to_string())]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_with_overrides() -> Result<()> {
+    let data = r#"
+prefix=/usr
+includedir=${prefix}/include
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I${includedir}/foo
+Libs: -L${prefix}/lib -Wl,-rpath,${libdir}
+    "#;
+
+    let overrides = HashMap::from([
+        ("prefix".to_string(), "/opt/foo".to_string()),
+        ("libdir".to_string(), "/opt/foo/lib64".to_string()),
+    ]);
+    let pkg_config = PkgConfigFile::parse_with_overrides(data, overrides)?;
+    assert_eq!(
+        pkg_config.includes,
+        vec!["/opt/foo/include/foo".to_string()]
+    );
+    assert_eq!(pkg_config.link_locations, vec!["/opt/foo/lib".to_string()]);
+    assert_eq!(
+        pkg_config.link_flags,
+        vec!["-Wl,-rpath,/opt/foo/lib64".to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_define_prefix() -> Result<()> {
+    let data = r#"prefix=/usr
+libdir=${prefix}/lib
+
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I${prefix}/include
+Libs: -L${libdir} -lfoo
+"#;
+    let path = Path::new("/opt/foo/lib/pkgconfig/foo.pc");
+    let options = ParseOptions {
+        define_prefix: true,
+        ..ParseOptions::default()
+    };
+    let (pkg_config, _) = PkgConfigFile::parse_with_options(data, &options.for_file(path), None)?;
+    assert_eq!(pkg_config.includes, ["/opt/foo/include"]);
+    assert_eq!(pkg_config.link_locations, ["/opt/foo/lib"]);
+
+    // the prefix is only known for files in a pkgconfig directory
+    assert_eq!(relocated_prefix(Path::new("/opt/foo/foo.pc")), None);
+    let (pkg_config, _) = PkgConfigFile::parse_with_options(
+        data,
+        &options.for_file(Path::new("/opt/foo/foo.pc")),
+        None,
+    )?;
+    assert_eq!(pkg_config.includes, ["/usr/include"]);
+
+    // a prefix defined explicitly wins
+    let mut options = options;
+    options
+        .define_variables
+        .insert("prefix".to_string(), "/srv".to_string());
+    let (pkg_config, _) = PkgConfigFile::parse_with_options(data, &options.for_file(path), None)?;
+    assert_eq!(pkg_config.includes, ["/srv/include"]);
+
+    // off by default
+    let (pkg_config, _) =
+        PkgConfigFile::parse_with_options(data, &ParseOptions::default().for_file(path), None)?;
+    assert_eq!(pkg_config.includes, ["/usr/include"]);
+    Ok(())
+}
+
+#[test]
+fn test_parse_error_variants() {
+    let missing_version = "Name: foo\nDescription: Foo library\n";
+    assert!(matches!(
+        PkgConfigFile::parse(missing_version),
+        Err(Error::MissingProperty(name)) if name == "Version"
+    ));
+
+    assert!(matches!(
+        expand_variables("foo=${foo}/${bar}\n", &HashMap::new(), None),
+        Err(Error::UnresolvableVariables { cyclic, undefined })
+            if cyclic == ["foo"] && undefined == ["bar"]
+    ));
+}
+
+#[test]
+fn test_long_variable_chain() -> Result<()> {
+    // Longer than the passes the expansion used to be limited to
+    let length = 150;
+    let mut data: String = (0..length)
+        .map(|i| format!("v{}=${{v{}}}/{}\n", i, i + 1, i))
+        .collect();
+    data.push_str(&format!("v{}=/root\n", length));
+    data.push_str("Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I$(v0)\n");
+
+    let mut trace = Vec::new();
+    let pkg_config = PkgConfigFile::parse_traced(&data, Some(&mut trace))?;
+    let expected: String = std::iter::once("/root".to_string())
+        .chain((0..length).rev().map(|i| format!("/{}", i)))
+        .collect();
+    assert_eq!(pkg_config.includes, [expected]);
+
+    // each variable is resolved once, the end of the chain first
+    let trace = String::from_utf8(trace)?;
+    let order = trace
+        .split("final variables:\n")
+        .next()
+        .unwrap_or_default()
+        .lines()
+        .skip(1)
+        .map(|line| {
+            line.trim()
+                .split(' ')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(order.len(), length + 1);
+    assert_eq!(order.first().map(String::as_str), Some("v150"));
+    assert_eq!(order.last().map(String::as_str), Some("v0"));
+    Ok(())
+}
+
+#[test]
+fn test_very_long_variable_chain() -> Result<()> {
+    // Deep enough to overflow the stack if each link of the chain were a recursive call
+    let length = 50_000;
+    let mut data: String = (0..length)
+        .map(|i| format!("v{}=${{v{}}}\n", i, i + 1))
+        .collect();
+    data.push_str(&format!("v{}=/root\n", length));
+    data.push_str("Name: foo\nDescription: Foo library\nVersion: 1.0\nCflags: -I${v0}\n");
+
+    assert_eq!(PkgConfigFile::parse(&data)?.includes, ["/root"]);
+
+    // and as long a cycle is still found
+    let data = data.replace(
+        &format!("v{}=/root", length),
+        &format!("v{}=${{v0}}", length),
+    );
+    assert!(matches!(
+        PkgConfigFile::parse(&data),
+        Err(Error::UnresolvableVariables { cyclic, .. }) if cyclic.len() == length + 1
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_cyclic_variables() {
+    let data = "prefix=/usr\na=${b}\nb=${a}/lib\nc=${a}\nd=${undefined}\n\
+                Name: foo\nDescription: Foo library\nVersion: 1.0\n\
+                Cflags: -I${prefix}/include -I${c} -I${d}\n";
+    match PkgConfigFile::parse(data) {
+        Err(Error::UnresolvableVariables { cyclic, undefined }) => {
+            assert_eq!(cyclic, ["a", "b"]);
+            assert_eq!(undefined, ["undefined"]);
+        }
+        result => panic!("expected UnresolvableVariables, got {:?}", result),
+    }
+
+    // A variable referring to an undefined one is left unexpanded, it is not an error
+    let data = data.replace("a=${b}", "a=/opt");
+    assert!(PkgConfigFile::parse(&data).is_ok());
+}
+
+#[test]
+fn test_parse_warnings() -> Result<()> {
+    let data = r#"
+prefix=/usr
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Requires: bar >=, baz
+Libs: -L${prefix}/lib -L${undefined}/lib -lfoo
+Libs.private:
+    "#;
+
+    let (pkg_config, warnings) = PkgConfigFile::parse_with_warnings(data)?;
+    assert_eq!(
+        pkg_config.link_locations,
+        vec!["/usr/lib".to_string(), "${undefined}/lib".to_string()]
+    );
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::UnexpandedVariable("undefined".to_string()),
+            Warning::EmptyProperty("Libs.private".to_string()),
+            Warning::MalformedDependency {
+                property: "Requires".to_string(),
+                entry: "bar >=".to_string(),
+            },
+        ]
+    );
+    assert_eq!(PkgConfigFile::parse(data)?, pkg_config);
+    Ok(())
+}
+
+#[test]
+fn test_parse_libs_private() -> Result<()> {
+    let data = r#"
+Name: png
+Description: Loads and saves PNG files
+Version: 1.6.37
+Libs: -L/usr/lib -lpng16
+Libs.private: -L/opt/zlib/lib -lz -lm -pthread
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(pkg_config.link_libraries, vec!["png16".to_string()]);
+    assert_eq!(
+        pkg_config.link_locations_private,
+        vec!["/opt/zlib/lib".to_string()]
+    );
+    assert_eq!(
+        pkg_config.link_libraries_private,
+        vec!["z".to_string(), "m".to_string()]
+    );
+    assert_eq!(pkg_config.link_flags_private, vec!["-pthread".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_parse_cflags_private() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo -DFOO
+Cflags.private: -I/opt/inc -DPRIV -fPIC
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(pkg_config.includes, vec!["/usr/include/foo".to_string()]);
+    assert_eq!(pkg_config.definitions, vec![Definition::parse("FOO")]);
+    assert_eq!(pkg_config.includes_private, vec!["/opt/inc".to_string()]);
+    assert_eq!(
+        pkg_config.definitions_private,
+        vec![Definition::parse("PRIV")]
+    );
+    assert_eq!(pkg_config.compile_flags_private, vec!["-fPIC".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_strict_undefined_variable() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I${undefined}/include
+    "#;
+
+    let strict = ParseOptions {
+        strict: true,
+        ..ParseOptions::default()
+    };
+    assert!(matches!(
+        PkgConfigFile::parse_with_options(data, &strict, None),
+        Err(Error::UndefinedVariable(name)) if name == "undefined"
+    ));
+    assert_eq!(
+        PkgConfigFile::parse(data)?.includes,
+        vec!["${undefined}/include".to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_definitions() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -DNDEBUG -DVERSION=2 -DPATH="/x y" -DEMPTY=
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(
+        pkg_config.definitions,
+        vec![
+            Definition {
+                name: "NDEBUG".to_string(),
+                value: None,
+            },
+            Definition {
+                name: "VERSION".to_string(),
+                value: Some("2".to_string()),
+            },
+            Definition {
+                name: "PATH".to_string(),
+                value: Some("/x y".to_string()),
+            },
+            Definition {
+                name: "EMPTY".to_string(),
+                value: Some(String::new()),
+            },
+        ]
+    );
+    let formatted: Vec<_> = pkg_config
+        .definitions
+        .iter()
+        .map(Definition::to_string)
+        .collect();
+    assert_eq!(formatted, ["NDEBUG", "VERSION=2", "PATH=/x y", "EMPTY="]);
+    assert!(pkg_config.compile_flags.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_split_flags() {
+    assert_eq!(
+        split_flags(r#"  -I/usr/include -DPATH="/x y"  -DNAME='a "b"' -DSPACE=a\ b "#),
+        [
+            "-I/usr/include",
+            "-DPATH=/x y",
+            r#"-DNAME=a "b""#,
+            "-DSPACE=a b"
+        ]
+    );
+    assert_eq!(
+        split_flags(r#"-DESCAPED="a \"b\" \n""#),
+        [r#"-DESCAPED=a "b" \n"#]
+    );
+    assert_eq!(split_flags(r#"-DEMPTY="""#), ["-DEMPTY="]);
+    assert!(split_flags("   ").is_empty());
+}
+
+#[test]
+fn test_link_flags_kept_intact() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Libs: -L/opt/lib -Wl,-rpath,/opt/lib -lfoo -pthread -Xlinker -L/opt/extra -framework CoreFoundation
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(pkg_config.link_locations, vec!["/opt/lib".to_string()]);
+    assert_eq!(pkg_config.link_libraries, vec!["foo".to_string()]);
+    assert_eq!(
+        pkg_config.link_flags,
+        vec![
+            "-Wl,-rpath,/opt/lib".to_string(),
+            "-pthread".to_string(),
+            "-Xlinker".to_string(),
+            "-L/opt/extra".to_string(),
+            "-framework".to_string(),
+            "CoreFoundation".to_string(),
+        ]
+    );
+    assert_eq!(pkg_config.frameworks, vec!["CoreFoundation".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_pthread_compile_and_link_flag() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo -pthread
+Libs: -lfoo
+Libs.private: -pthreads
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(pkg_config.compile_flags, vec!["-pthread".to_string()]);
+    assert_eq!(pkg_config.link_flags, vec!["-pthread".to_string()]);
+    assert_eq!(
+        pkg_config.compile_flags_private,
+        vec!["-pthreads".to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_isystem() -> Result<()> {
+    let data = r#"
+Name: foo
+Description: Foo library
+Version: 1.0.0
+Cflags: -I/usr/include/foo -isystem /opt/inc -isystem/opt/other -Wall
+    "#;
+
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(
+        pkg_config.includes,
+        vec![
+            "/usr/include/foo".to_string(),
+            "/opt/inc".to_string(),
+            "/opt/other".to_string(),
+        ]
+    );
+    assert_eq!(pkg_config.compile_flags, vec!["-Wall".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_parse_crlf_and_bom() -> Result<()> {
+    let expected = PkgConfigFile {
+        name: "foo".to_string(),
+        description: "Foo library".to_string(),
+        version: "1.0.0".to_string(),
+        requires: vec![Dependency::from_name("bar")],
+        link_locations: vec!["/usr/lib".to_string()],
+        link_libraries: vec!["foo".to_string()],
+        ..PkgConfigFile::default()
+    };
+    let data = "prefix=/usr\nlibdir=${prefix}/lib\n\nName: foo\nDescription: Foo library\n\
+                Version: 1.0.0\nRequires: bar\nLibs: -L${libdir} -lfoo\n";
+
+    let crlf = data.replace('\n', "\r\n");
+    assert_eq!(PkgConfigFile::parse(&crlf)?, expected);
+
+    let bom = format!("\u{feff}{}", data);
+    assert_eq!(PkgConfigFile::parse(&bom)?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_extra_properties() -> Result<()> {
+    let data = "prefix=/usr\nName: foo\nDescription: Foo library\nVersion: 1.0\n\
+                X-Homepage: https://example.com/foo\nX-Vendor-Id:\tfoo-${prefix}\n\
+                X-Empty:\nLibs: -lfoo\n";
+    let pkg_config = PkgConfigFile::parse(data)?;
+    assert_eq!(
+        pkg_config.extra,
+        HashMap::from([
+            (
+                "X-Homepage".to_string(),
+                "https://example.com/foo".to_string()
+            ),
+            ("X-Vendor-Id".to_string(), "foo-/usr".to_string()),
+        ])
+    );
+    Ok(())
+}
+
 #[test]
 fn test_capture_property() -> Result<()> {
     let data = r#"
@@ -331,8 +1506,44 @@ Cflags: -I${includedir}
     Ok(())
 }
 
+#[test]
+fn test_parse_whitespace_separated_dependency_list() -> Result<()> {
+    let expected = vec![
+        Dependency::with_version("glib-2.0", ">=", "2.50"),
+        Dependency::from_name("gobject-2.0"),
+        Dependency::with_version("zlib", "<", "2"),
+    ];
+    assert_eq!(
+        Dependency::parse_list("glib-2.0\t>=\t2.50\tgobject-2.0\tzlib<\t2"),
+        expected
+    );
+    assert_eq!(
+        Dependency::parse_list("glib-2.0 >=\n  2.50,\n\tgobject-2.0\n zlib < 2\n"),
+        expected
+    );
+
+    let pkg_config = PkgConfigFile::parse(
+        "Name: foo\nDescription: Foo\nVersion: 1.0\nRequires:\tglib-2.0\t>=\t2.50\tgobject-2.0\n",
+    )?;
+    assert_eq!(pkg_config.requires, expected[..2]);
+    Ok(())
+}
+
 #[test]
 fn test_parse_dependency_list() -> Result<()> {
+    let foo_range = || Dependency {
+        name: "foo".to_string(),
+        constraints: vec![
+            VersionConstraint {
+                op: ">=".to_string(),
+                version: Some("1.0".to_string()),
+            },
+            VersionConstraint {
+                op: "<".to_string(),
+                version: Some("2.0".to_string()),
+            },
+        ],
+    };
     let dependency_lists = [
         "ACE_ETCL",
         "freetype2 >= 21.0.15",
@@ -343,6 +1554,12 @@ fn test_parse_dependency_list() -> Result<()> {
         "nspr, nss",
         "xproto x11",
         "",
+        "glib-2.0,",
+        "glib-2.0, , gio-2.0",
+        "foo >=",
+        "foo, >= 1.0, bar",
+        "foo >= 1.0 foo < 2.0",
+        "foo >= 1.0, bar, foo < 2.0",
     ];
     let expected = [
         vec![Dependency::from_name("ACE_ETCL")],
@@ -366,6 +1583,21 @@ fn test_parse_dependency_list() -> Result<()> {
             Dependency::from_name("x11"),
         ],
         vec![],
+        vec![Dependency::from_name("glib-2.0")],
+        vec![
+            Dependency::from_name("glib-2.0"),
+            Dependency::from_name("gio-2.0"),
+        ],
+        vec![Dependency {
+            name: "foo".to_string(),
+            constraints: vec![VersionConstraint {
+                op: ">=".to_string(),
+                version: None,
+            }],
+        }],
+        vec![Dependency::from_name("foo"), Dependency::from_name("bar")],
+        vec![foo_range()],
+        vec![foo_range(), Dependency::from_name("bar")],
     ];
 
     for (dependency_list, expected) in dependency_lists.iter().zip(expected.iter()) {
@@ -0,0 +1,116 @@
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::cps::Platform;
+use crate::lib_search::get_target_triple;
+
+/// Kernels recognized in a target triple, with the CPS name of each
+const KERNELS: &[(&str, &str)] = &[
+    ("linux", "linux"),
+    ("darwin", "darwin"),
+    ("freebsd", "freebsd"),
+    ("netbsd", "netbsd"),
+    ("openbsd", "openbsd"),
+    ("windows", "windows"),
+    ("mingw32", "windows"),
+    ("cygwin", "windows"),
+];
+
+/// The `isa` and `kernel` of a target triple like `x86_64-linux-gnu` or
+/// `aarch64-apple-darwin23.1.0`, leaving out whatever isn't recognized
+pub fn parse_triple(triple: &str) -> Platform {
+    let mut parts = triple.trim().split('-');
+    let isa = parts.next().filter(|isa| !isa.is_empty()).map(String::from);
+    let kernel = parts.find_map(|part| {
+        KERNELS.iter().find_map(|(prefix, kernel)| {
+            let version = part.strip_prefix(prefix)?;
+            Some((kernel, version))
+        })
+    });
+    Platform {
+        isa,
+        kernel: kernel.map(|(kernel, _)| kernel.to_string()),
+        kernel_version: kernel
+            .map(|(_, version)| version)
+            .filter(|version| !version.is_empty())
+            .map(String::from),
+        ..Platform::default()
+    }
+}
+
+/// The C runtime vendor and version from the output of `ldd --version`
+///
+/// glibc prints e.g. `ldd (GNU libc) 2.39` on its first line, musl prints `Version 1.2.4` on
+/// a line of its own.
+pub fn parse_ldd_version(output: &str) -> Option<(String, String)> {
+    let first_line = output.lines().next()?;
+    if first_line.contains("GLIBC") || first_line.contains("GNU libc") {
+        let version = first_line.split_whitespace().last()?;
+        return Some(("gnu".to_string(), version.to_string()));
+    }
+    if output.contains("musl") {
+        let version = output
+            .lines()
+            .find_map(|line| line.strip_prefix("Version "))?;
+        return Some(("musl".to_string(), version.trim().to_string()));
+    }
+    None
+}
+
+fn get_c_runtime() -> Option<(String, String)> {
+    // musl prints its version to stderr and exits with an error
+    let output = Command::new("ldd").arg("--version").output().ok()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    parse_ldd_version(&text)
+}
+
+/// The platform of the host, with only the fields that could be determined
+pub fn detect() -> Platform {
+    static PLATFORM: OnceLock<Platform> = OnceLock::new();
+    PLATFORM
+        .get_or_init(|| {
+            let mut platform = get_target_triple().map(parse_triple).unwrap_or_default();
+            if let Some((vendor, version)) = get_c_runtime() {
+                platform.c_runtime_vendor = Some(vendor);
+                platform.c_runtime_version = Some(version);
+            }
+            platform
+        })
+        .clone()
+}
+
+#[test]
+fn test_parse_triple() {
+    let platform = parse_triple("x86_64-linux-gnu\n");
+    assert_eq!(platform.isa.as_deref(), Some("x86_64"));
+    assert_eq!(platform.kernel.as_deref(), Some("linux"));
+    assert_eq!(platform.kernel_version, None);
+
+    let platform = parse_triple("aarch64-apple-darwin23.1.0");
+    assert_eq!(platform.isa.as_deref(), Some("aarch64"));
+    assert_eq!(platform.kernel.as_deref(), Some("darwin"));
+    assert_eq!(platform.kernel_version.as_deref(), Some("23.1.0"));
+
+    let platform = parse_triple("x86_64-w64-mingw32");
+    assert_eq!(platform.kernel.as_deref(), Some("windows"));
+
+    let platform = parse_triple("riscv64-unknown-elf");
+    assert_eq!(platform.isa.as_deref(), Some("riscv64"));
+    assert_eq!(platform.kernel, None);
+}
+
+#[test]
+fn test_parse_ldd_version() {
+    let glibc = "ldd (Ubuntu GLIBC 2.35-0ubuntu3.1) 2.35\nCopyright (C) 2022 Free Software Foundation, Inc.\n";
+    assert_eq!(
+        parse_ldd_version(glibc),
+        Some(("gnu".to_string(), "2.35".to_string()))
+    );
+    let musl = "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Loader\n";
+    assert_eq!(
+        parse_ldd_version(musl),
+        Some(("musl".to_string(), "1.2.4".to_string()))
+    );
+    assert_eq!(parse_ldd_version("ldd: unknown option\n"), None);
+}
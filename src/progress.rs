@@ -0,0 +1,75 @@
+use std::io::IsTerminal;
+
+/// Progress of converting a number of files, drawn on stderr when it is a terminal
+///
+/// Without the `progress` feature nothing is drawn.
+pub(crate) struct Progress {
+    #[cfg(feature = "progress")]
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    /// Progress over `total` files, drawn only if `enabled` and stderr is a terminal
+    pub fn new(total: usize, enabled: bool) -> Self {
+        let visible = enabled && std::io::stderr().is_terminal();
+        #[cfg(feature = "progress")]
+        {
+            let bar = visible.then(|| {
+                let bar = indicatif::ProgressBar::new(total as u64);
+                bar.set_style(
+                    indicatif::ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}")
+                        .expect("progress template is valid"),
+                );
+                bar
+            });
+            Self { bar }
+        }
+        #[cfg(not(feature = "progress"))]
+        {
+            let _ = (total, visible);
+            Self {}
+        }
+    }
+
+    #[cfg(test)]
+    fn is_visible(&self) -> bool {
+        #[cfg(feature = "progress")]
+        return self.bar.is_some();
+        #[cfg(not(feature = "progress"))]
+        false
+    }
+
+    /// Show `name` as the file being converted
+    pub fn start(&self, name: &str) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.set_message(name.to_string());
+        }
+        #[cfg(not(feature = "progress"))]
+        let _ = name;
+    }
+
+    /// Count a file as converted
+    pub fn inc(&self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    pub fn finish(&self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[test]
+fn test_progress_hidden_when_disabled() {
+    let progress = Progress::new(10, false);
+    assert!(!progress.is_visible());
+    progress.start("foo.pc");
+    progress.inc();
+    progress.finish();
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A uniquely named directory under the system temp dir that is removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "cps-deps-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).expect("failed to create temp dir");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write `contents` to a path relative to the temp dir, creating parent directories
+    pub fn write(&self, relative: impl AsRef<Path>, contents: &str) -> PathBuf {
+        let path = self.0.join(relative);
+        fs::create_dir_all(path.parent().expect("path has a parent"))
+            .expect("failed to create parent dir");
+        fs::write(&path, contents).expect("failed to write file");
+        path
+    }
+
+    /// Write a minimal pc file of the package `name` to a path relative to the temp dir
+    pub fn write_pc(&self, relative: impl AsRef<Path>, name: &str) -> PathBuf {
+        self.write(
+            relative,
+            &format!("Name: {}\nDescription: {}\nVersion: 1.0\n", name, name),
+        )
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,198 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// The `version_schema` of a package, which says how its versions are ordered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSchema {
+    /// Dot separated numbers, where missing trailing parts are zero so `1.0` equals `1.0.0`
+    #[default]
+    Simple,
+    /// `[epoch:]version[-release]` compared like `rpmvercmp`
+    Rpm,
+    /// Debian versions, which this crate can't compare
+    Dpkg,
+    /// Versions only the package's own tooling can compare
+    Custom,
+}
+
+impl FromStr for VersionSchema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "simple" => Ok(Self::Simple),
+            "rpm" => Ok(Self::Rpm),
+            "dpkg" => Ok(Self::Dpkg),
+            "custom" => Ok(Self::Custom),
+            _ => Err(format!("unknown version schema `{}`", s)),
+        }
+    }
+}
+
+/// Compare the versions `a` and `b` under `schema`
+///
+/// Returns `None` when the schema has no ordering this crate implements.
+pub fn compare(a: &str, b: &str, schema: VersionSchema) -> Option<Ordering> {
+    match schema {
+        VersionSchema::Simple => Some(compare_simple(a, b)),
+        VersionSchema::Rpm => Some(compare_rpm(a, b)),
+        VersionSchema::Dpkg | VersionSchema::Custom => None,
+    }
+}
+
+/// Split a part of a simple version into its leading number and the rest, e.g. `3rc1` into
+/// `(3, "rc1")`
+fn simple_part(part: &str) -> (u64, &str) {
+    let digits = part
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(part.len());
+    (part[..digits].parse().unwrap_or(0), &part[digits..])
+}
+
+fn compare_simple(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (a_part, b_part) => {
+                let ordering =
+                    simple_part(a_part.unwrap_or("0")).cmp(&simple_part(b_part.unwrap_or("0")));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// Split an rpm version into its epoch, version and release, e.g. `1:2.0-3` into
+/// `(1, "2.0", Some("3"))`
+fn rpm_parts(version: &str) -> (u64, &str, Option<&str>) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+        None => (0, version),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+fn compare_rpm(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_version, a_release) = rpm_parts(a);
+    let (b_epoch, b_version, b_release) = rpm_parts(b);
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| rpmvercmp(a_version, b_version))
+        .then_with(|| match (a_release, b_release) {
+            // A missing release matches any release
+            (Some(a_release), Some(b_release)) => rpmvercmp(a_release, b_release),
+            _ => Ordering::Equal,
+        })
+}
+
+/// The segment comparison of rpm: runs of digits compare numerically and are newer than runs of
+/// letters, which compare lexically, and a `~` sorts before anything, even the end
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    let separator = |c: char| !c.is_ascii_alphanumeric() && c != '~';
+    let (mut a, mut b) = (a, b);
+    loop {
+        a = a.trim_start_matches(separator);
+        b = b.trim_start_matches(separator);
+
+        match (a.strip_prefix('~'), b.strip_prefix('~')) {
+            (Some(a_rest), Some(b_rest)) => {
+                a = a_rest;
+                b = b_rest;
+                continue;
+            }
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => {}
+        }
+
+        if a.is_empty() || b.is_empty() {
+            return a.len().cmp(&b.len());
+        }
+
+        let numeric = a.starts_with(|c: char| c.is_ascii_digit());
+        let segment_end = |s: &str| {
+            s.find(|c: char| {
+                if numeric {
+                    !c.is_ascii_digit()
+                } else {
+                    !c.is_ascii_alphabetic()
+                }
+            })
+            .unwrap_or(s.len())
+        };
+        let a_segment = &a[..segment_end(a)];
+        let b_segment = &b[..segment_end(b)];
+
+        if b_segment.is_empty() {
+            // The segments are of different kinds, numbers are newer than letters
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let ordering = if numeric {
+            let a_digits = a_segment.trim_start_matches('0');
+            let b_digits = b_segment.trim_start_matches('0');
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            a_segment.cmp(b_segment)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a = &a[a_segment.len()..];
+        b = &b[b_segment.len()..];
+    }
+}
+
+#[test]
+fn test_compare_simple() {
+    let cmp = |a, b| compare(a, b, VersionSchema::Simple);
+    assert_eq!(cmp("1.0", "1.0.0"), Some(Ordering::Equal));
+    assert_eq!(cmp("1.2", "1.10"), Some(Ordering::Less));
+    assert_eq!(cmp("2.0", "1.99.99"), Some(Ordering::Greater));
+    assert_eq!(cmp("1.0.1", "1.0"), Some(Ordering::Greater));
+    assert_eq!(cmp("1.2.3rc1", "1.2.3"), Some(Ordering::Greater));
+    assert_eq!(cmp("3", "3.0.0.0"), Some(Ordering::Equal));
+}
+
+#[test]
+fn test_compare_rpm() {
+    let cmp = |a, b| compare(a, b, VersionSchema::Rpm);
+    assert_eq!(cmp("1.0", "1.0"), Some(Ordering::Equal));
+    assert_eq!(cmp("1.0", "1.0.0"), Some(Ordering::Less));
+    assert_eq!(cmp("1.10", "1.9"), Some(Ordering::Greater));
+    assert_eq!(cmp("1.0a", "1.0"), Some(Ordering::Greater));
+    assert_eq!(cmp("1.0a", "1.0.1"), Some(Ordering::Less));
+    assert_eq!(cmp("1.001", "1.1"), Some(Ordering::Equal));
+    assert_eq!(cmp("1.0~rc1", "1.0"), Some(Ordering::Less));
+    assert_eq!(cmp("1.0~rc1", "1.0~rc2"), Some(Ordering::Less));
+
+    // The epoch wins over the version
+    assert_eq!(cmp("1:1.0", "2.0"), Some(Ordering::Greater));
+    assert_eq!(cmp("0:2.0", "2.0"), Some(Ordering::Equal));
+    assert_eq!(cmp("1:1.0", "2:0.1"), Some(Ordering::Less));
+
+    assert_eq!(cmp("1.0-2", "1.0-10"), Some(Ordering::Less));
+    assert_eq!(cmp("1.0-2", "1.0"), Some(Ordering::Equal));
+}
+
+#[test]
+fn test_compare_unsupported_schema() {
+    assert_eq!("dpkg".parse(), Ok(VersionSchema::Dpkg));
+    assert_eq!(compare("1.0", "2.0", VersionSchema::Dpkg), None);
+    assert_eq!(compare("1.0", "2.0", VersionSchema::Custom), None);
+    assert!("semver".parse::<VersionSchema>().is_err());
+}
@@ -0,0 +1,46 @@
+use std::process::Command;
+
+/// The triple the host compiler reports, if there is one
+fn host_triple() -> Option<String> {
+    ["gcc", "clang"].iter().find_map(|compiler| {
+        let output = Command::new(compiler).arg("-dumpmachine").output().ok()?;
+        let triple = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        (output.status.success() && !triple.is_empty()).then_some(triple)
+    })
+}
+
+#[test]
+fn test_doctor_prints_triple() {
+    let Some(triple) = host_triple() else {
+        return;
+    };
+    let search_dir = std::env::temp_dir();
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .arg("doctor")
+        .arg("--search-dir")
+        .arg(&search_dir)
+        .output()
+        .expect("failed to run cps-deps");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("Target triple: {}", triple)),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(&format!("{} (exists)", search_dir.display())),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_doctor_fails_without_discovery_roots() {
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .args(["doctor", "--search-dir", "/nonexistent/cps-deps-doctor"])
+        .output()
+        .expect("failed to run cps-deps");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,34 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_explain_converts_once() {
+    let pc = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/explain/foo.pc");
+    let cps = std::env::temp_dir().join(format!("cps-deps-explain-{}.cps", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .arg("generate")
+        .arg(&pc)
+        .arg(&cps)
+        .args(["--explain", "--spdx-license"])
+        .output()
+        .expect("failed to run cps-deps");
+    let written = fs::read_to_string(&cps);
+    let _ = fs::remove_file(&cps);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("Flag `-std=c++17` of `Cflags` became the compile feature `c++17`"),
+        "{}",
+        stderr
+    );
+    // the package explained is the one written, the warnings of the conversion show once
+    assert_eq!(
+        stderr.matches("has no known SPDX identifier").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(written.is_ok_and(|json| json.contains("\"c++17\"")));
+}
@@ -0,0 +1,30 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_progress_suppressed_when_piped() {
+    let dir = std::env::temp_dir().join(format!("cps-deps-progress-{}", std::process::id()));
+    let pc_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/progress");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .arg("generate-all")
+        .arg(dir.join("out"))
+        .arg("--search-dir")
+        .arg(&pc_dir)
+        .arg("--progress")
+        .output()
+        .expect("failed to run cps-deps");
+    let written = fs::read_dir(dir.join("out")).map(|entries| entries.count());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(written.ok(), Some(2));
+    // stderr is a pipe, not a terminal, so nothing is drawn on it
+    assert!(output.stdout.is_empty());
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
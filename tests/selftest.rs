@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_selftest_reports_failures() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/selftest");
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .arg("selftest")
+        .arg("--search-dir")
+        .arg(&fixtures)
+        .output()
+        .expect("failed to run cps-deps");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stdout.contains("1 passed, 1 failed"), "{}", stdout);
+    assert!(stderr.contains("missing.pc"), "{}", stderr);
+    assert!(!stderr.contains("eigen3.pc"), "{}", stderr);
+}
+
+#[test]
+fn test_selftest_passes() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/selftest");
+    let output = Command::new(env!("CARGO_BIN_EXE_cps-deps"))
+        .arg("selftest")
+        .arg("--search-dir")
+        .arg(&fixtures)
+        .args(["--exclude", "missing"])
+        .output()
+        .expect("failed to run cps-deps");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("1 passed, 0 failed"), "{}", stdout);
+}